@@ -0,0 +1,215 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use amethyst::{
+    core::transform::Transform,
+    ecs::prelude::{Entities, Entity, LazyUpdate, Read, ReadExpect, System, WriteExpect},
+    renderer::{sprite::SpriteSheetHandle, SpriteRender},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sprite::{AssetType, SpriteSheetMap},
+    tile_map::{tile_center, TileCatalog, TileMap, TileType},
+};
+
+// The bit each neighbor adds to a tile's mask
+const NEIGHBOR_BITS: [((i32, i32), u8); 4] = [((0, 1), 1), ((1, 0), 2), ((0, -1), 4), ((-1, 0), 8)];
+
+/** A floor sprite, turned to fit the tile's neighbors */
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct AutotileSprite {
+    pub sprite_number: usize,
+    // Counterclockwise quarter turns, so one sprite covers every rotation of a shape
+    #[serde(default)]
+    pub quarter_turns: u8,
+}
+
+/** How one tile type picks its sprite from the tiles around it */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AutotileRule {
+    // Neighbor tile types the tile joins up with
+    pub connects_to: Vec<TileType>,
+    // The sprite for each mask of joined neighbors: 1 up, 2 right, 4 down and
+    // 8 left. Masks that aren't listed use the tile's own sprite.
+    pub sprites: BTreeMap<u8, AutotileSprite>,
+}
+
+/** Autotiling rules for the tile types that have them, loaded from `assets/autotile.ron` */
+#[derive(Default, Deserialize, Serialize)]
+pub struct AutotileRules {
+    pub rules: BTreeMap<TileType, AutotileRule>,
+}
+
+impl AutotileRules {
+    pub fn sprite(&self, tile_map: &TileMap, coord: (i32, i32)) -> Option<AutotileSprite> {
+        let rule = self.rules.get(&tile_map.get(coord)?)?;
+        let mask = NEIGHBOR_BITS
+            .iter()
+            .filter(|((x, y), _)| {
+                tile_map
+                    .get((coord.0 + x, coord.1 + y))
+                    .map_or(false, |neighbor| rule.connects_to.contains(&neighbor))
+            })
+            .fold(0, |mask, (_, bit)| mask | bit);
+        rule.sprites.get(&mask).cloned()
+    }
+}
+
+// Draws the floor. The whole map is drawn on the first run, after that only
+// tiles that changed and their neighbors are redrawn.
+#[derive(Default)]
+pub struct AutotileSystem {
+    floor_tiles: HashMap<(i32, i32), Vec<Entity>>,
+    drawn: bool,
+}
+
+impl<'s> System<'s> for AutotileSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteExpect<'s, TileMap>,
+        ReadExpect<'s, TileCatalog>,
+        ReadExpect<'s, AutotileRules>,
+        ReadExpect<'s, LazyUpdate>,
+        Read<'s, SpriteSheetMap>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut tile_map, tile_catalog, autotile_rules, lazy_update, sprite_sheet_map) =
+            data;
+
+        let sprite_sheet = match sprite_sheet_map.get(AssetType::Floor) {
+            Some(sprite_sheet) => sprite_sheet,
+            None => return,
+        };
+        let changed = tile_map.take_changed();
+        let coords = if self.drawn {
+            // A tile's sprite depends on its neighbors, so they are redrawn too
+            changed
+                .into_iter()
+                .flat_map(|coord| {
+                    NEIGHBOR_BITS
+                        .iter()
+                        .map(move |((x, y), _)| (coord.0 + x, coord.1 + y))
+                        .chain(Some(coord))
+                })
+                .filter(|coord| tile_map.is_within(*coord))
+                .collect::<HashSet<_>>()
+        } else {
+            self.drawn = true;
+            (0..tile_map.width)
+                .flat_map(|x| (0..tile_map.height).map(move |y| (x, y)))
+                .collect()
+        };
+
+        for coord in coords {
+            for entity in self.floor_tiles.remove(&coord).unwrap_or_default() {
+                let _ = entities.delete(entity);
+            }
+            let definition = match tile_map.get(coord).and_then(|tile| tile_catalog.get(tile)) {
+                Some(definition) => definition,
+                None => continue,
+            };
+            let sprite = autotile_rules
+                .sprite(&tile_map, coord)
+                .unwrap_or(AutotileSprite {
+                    sprite_number: definition.sprite_number,
+                    quarter_turns: 0,
+                });
+            let floor_tile = create_floor_tile(
+                &entities,
+                &lazy_update,
+                sprite_sheet,
+                coord,
+                sprite,
+                definition.overlay_sprite_number,
+            );
+            self.floor_tiles.insert(coord, floor_tile);
+        }
+    }
+}
+
+fn create_floor_tile(
+    entities: &Entities,
+    lazy_update: &ReadExpect<LazyUpdate>,
+    sprite_sheet: &SpriteSheetHandle,
+    coord: (i32, i32),
+    sprite: AutotileSprite,
+    overlay_sprite_number: Option<usize>,
+) -> Vec<Entity> {
+    let mut transform = Transform::default();
+    let mut position = tile_center(coord);
+    position.z = -1.0;
+    transform.set_translation(position);
+    transform.set_rotation_2d(f32::from(sprite.quarter_turns) * std::f32::consts::FRAC_PI_2);
+
+    let floor_sprite = SpriteRender {
+        sprite_sheet: sprite_sheet.clone(),
+        sprite_number: sprite.sprite_number,
+    };
+
+    let floor = entities.create();
+    lazy_update.insert(floor, floor_sprite);
+    lazy_update.insert(floor, transform);
+    let mut floor_tile = vec![floor];
+
+    if let Some(overlay_sprite_number) = overlay_sprite_number {
+        let mut transform = Transform::default();
+        // Just above the floor
+        position.z = -0.9;
+        transform.set_translation(position);
+
+        let overlay_sprite = SpriteRender {
+            sprite_sheet: sprite_sheet.clone(),
+            sprite_number: overlay_sprite_number,
+        };
+
+        let overlay = entities.create();
+        lazy_update.insert(overlay, overlay_sprite);
+        lazy_update.insert(overlay, transform);
+        floor_tile.push(overlay);
+    }
+    floor_tile
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::config::Config;
+
+    use super::*;
+    use crate::tile_map::parse_map;
+
+    fn sprite_at(tile_map: &TileMap, coord: (i32, i32)) -> Option<(usize, u8)> {
+        let autotile_rules = AutotileRules::load_no_fallback(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/autotile.ron"
+        ))
+        .unwrap();
+        autotile_rules
+            .sprite(tile_map, coord)
+            .map(|sprite| (sprite.sprite_number, sprite.quarter_turns))
+    }
+
+    #[test]
+    fn grass_next_to_the_path_gets_an_edge() {
+        let (tile_map, _) = parse_map(
+            "\
+..#E
+S##.
+....
+",
+        )
+        .unwrap();
+        // Path to the right and below
+        assert_eq!(sprite_at(&tile_map, (1, 2)), Some((16, 3)));
+        // The goal above and path to the left
+        assert_eq!(sprite_at(&tile_map, (3, 1)), Some((16, 1)));
+        // The spawn below
+        assert_eq!(sprite_at(&tile_map, (0, 2)), Some((15, 2)));
+        // Path above
+        assert_eq!(sprite_at(&tile_map, (2, 0)), Some((15, 0)));
+        // Only grass around
+        assert_eq!(sprite_at(&tile_map, (3, 0)), None);
+        // The path itself still picks path sprites
+        assert_eq!(sprite_at(&tile_map, (1, 1)), Some((8, 1)));
+    }
+}
@@ -0,0 +1,54 @@
+use amethyst::{
+    core::{math::Vector3, timing::Time, transform::Transform},
+    ecs::prelude::{Entities, Join, Read, ReadExpect, ReadStorage, System, WriteStorage},
+};
+
+use crate::{
+    enemy::Enemy,
+    projectile::Projectile,
+    tile_map::{TileMap, TILE_SIZE},
+};
+
+// How far outside the map an entity can go before it is removed. Leaves room
+// for arcing projectiles that briefly fly above the top edge.
+const OFF_MAP_MARGIN: f32 = 2.0 * TILE_SIZE;
+
+// Removes projectiles that missed and anything that left the map
+pub struct CleanupSystem;
+
+impl<'s> System<'s> for CleanupSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Projectile>,
+        ReadStorage<'s, Enemy>,
+        ReadStorage<'s, Transform>,
+        ReadExpect<'s, TileMap>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut projectiles, enemies, transforms, tile_map, time) = data;
+        for (entity, projectile, transform) in (&entities, &mut projectiles, &transforms).join() {
+            projectile.age(time.fixed_seconds());
+            if projectile.is_spent(transform.translation())
+                || is_off_map(&tile_map, transform.translation())
+            {
+                let _ = entities.delete(entity);
+            }
+        }
+        for (entity, _enemy, transform) in (&entities, &enemies, &transforms).join() {
+            if is_off_map(&tile_map, transform.translation()) {
+                let _ = entities.delete(entity);
+            }
+        }
+    }
+}
+
+fn is_off_map(tile_map: &TileMap, position: &Vector3<f32>) -> bool {
+    let width = tile_map.width as f32 * TILE_SIZE;
+    let height = tile_map.height as f32 * TILE_SIZE;
+    position.x < -OFF_MAP_MARGIN
+        || position.x > width + OFF_MAP_MARGIN
+        || position.y < -OFF_MAP_MARGIN
+        || position.y > height + OFF_MAP_MARGIN
+}
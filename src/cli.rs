@@ -0,0 +1,54 @@
+use std::{env, path::PathBuf};
+
+use amethyst::error::Error;
+
+use crate::map_generator::GeneratorKind;
+
+#[derive(Default)]
+pub struct CliArgs {
+    pub seed: Option<u64>,
+    // Play generated waves until the player runs out of lives
+    pub endless: bool,
+    // Level file to play instead of a generated map
+    pub level: Option<PathBuf>,
+    // Where to save the level being played
+    pub export_level: Option<PathBuf>,
+    // Overrides the generator picked in the map config
+    pub map_generator: Option<GeneratorKind>,
+    // Map config to use instead of `assets/map.ron`
+    pub map_config: Option<PathBuf>,
+}
+
+impl CliArgs {
+    pub fn parse() -> Result<Self, Error> {
+        let mut cli_args = CliArgs::default();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let seed = value
+                        .parse::<u64>()
+                        .map_err(|_| Error::from_string(format!("Invalid seed: {}", value)))?;
+                    cli_args.seed = Some(seed);
+                }
+                "--endless" => cli_args.endless = true,
+                "--level" => cli_args.level = Some(next_value(&mut args, &arg)?.into()),
+                "--export-level" => {
+                    cli_args.export_level = Some(next_value(&mut args, &arg)?.into())
+                }
+                "--map-generator" => {
+                    cli_args.map_generator = Some(next_value(&mut args, &arg)?.parse()?)
+                }
+                "--map-config" => cli_args.map_config = Some(next_value(&mut args, &arg)?.into()),
+                _ => return Err(Error::from_string(format!("Unknown argument: {}", arg))),
+            }
+        }
+        Ok(cli_args)
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, Error> {
+    args.next()
+        .ok_or_else(|| Error::from_string(format!("Missing value for {}", flag)))
+}
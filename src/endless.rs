@@ -0,0 +1,255 @@
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    enemy::{EnemyCatalog, EnemyType},
+    spawner::SpawnerId,
+    wave::{WaveDefinition, WaveGroup},
+};
+
+// How a wave's difficulty budget grows with the wave number
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum BudgetCurve {
+    // `base + increment * (wave - 1)`
+    Linear { base: f32, increment: f32 },
+    // `base * growth ^ (wave - 1)`
+    Exponential { base: f32, growth: f32 },
+}
+
+impl Default for BudgetCurve {
+    fn default() -> Self {
+        BudgetCurve::Linear {
+            base: 30.0,
+            increment: 10.0,
+        }
+    }
+}
+
+impl BudgetCurve {
+    pub fn budget(&self, wave_number: usize) -> f32 {
+        let waves_in = (wave_number.max(1) - 1) as f32;
+        match *self {
+            BudgetCurve::Linear { base, increment } => base + increment * waves_in,
+            BudgetCurve::Exponential { base, growth } => base * growth.powf(waves_in),
+        }
+    }
+}
+
+/** Settings for endless mode's wave generator, loaded from `assets/endless.ron` */
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct EndlessConfig {
+    // Seconds to build before each wave
+    pub build_time: f32,
+    pub budget: BudgetCurve,
+    // Most enemies bought as one group
+    pub max_group_size: u32,
+    // Seconds between two enemies of a group
+    pub spacing: f32,
+    // Seconds between the last enemy of a group and the first of the next
+    pub group_gap: f32,
+    // Every `elite_interval`th wave opens with a group of elites
+    pub elite_interval: usize,
+    // How many times the regular wave cost an elite costs
+    pub elite_cost_multiplier: f32,
+    // Every `boss_interval`th wave ends with a boss
+    pub boss_interval: usize,
+}
+
+impl EndlessConfig {
+    // Buys groups of enemies from the catalog until the wave's budget runs out,
+    // sending each group from one of `spawners`. The same `rng` state always
+    // produces the same wave.
+    pub fn generate_wave<R: Rng>(
+        &self,
+        wave_number: usize,
+        enemy_catalog: &EnemyCatalog,
+        spawners: &[SpawnerId],
+        rng: &mut R,
+    ) -> WaveDefinition {
+        let mut budget = self.budget.budget(wave_number);
+        let mut regulars = Vec::new();
+        let mut bosses = Vec::new();
+        for (enemy_type, definition) in enemy_catalog.enemies.iter() {
+            match definition.wave_cost {
+                Some(wave_cost) if wave_cost > 0 => {
+                    if definition.boss {
+                        bosses.push((enemy_type, wave_cost as f32));
+                    } else {
+                        regulars.push((enemy_type, wave_cost as f32));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // The boss is paid for first so the rest of the wave is lighter
+        let boss = if is_every(wave_number, self.boss_interval) {
+            bosses.choose(rng).map(|&(enemy_type, wave_cost)| {
+                budget -= wave_cost;
+                enemy_type.clone()
+            })
+        } else {
+            None
+        };
+
+        let mut groups = Vec::new();
+        let mut delay = 0.0;
+        let mut elite = is_every(wave_number, self.elite_interval);
+        loop {
+            let cost_multiplier = if elite {
+                self.elite_cost_multiplier
+            } else {
+                1.0
+            };
+            let affordable = regulars
+                .iter()
+                .filter(|(_, wave_cost)| wave_cost * cost_multiplier <= budget)
+                .collect::<Vec<_>>();
+            let (enemy_type, wave_cost) = match affordable.choose(rng) {
+                Some(&&(enemy_type, wave_cost)) => (enemy_type, wave_cost * cost_multiplier),
+                None if elite => {
+                    // Fill the wave with regular enemies if no elite fits
+                    elite = false;
+                    continue;
+                }
+                None => break,
+            };
+
+            let max_count = ((budget / wave_cost) as u32)
+                .min(self.max_group_size)
+                .max(1);
+            let count = rng.gen_range((max_count + 1) / 2, max_count + 1);
+            budget -= wave_cost * count as f32;
+            let spawner = pick_spawner(spawners, rng);
+            groups.push(new_group(
+                enemy_type,
+                count,
+                spawner,
+                self.spacing,
+                delay,
+                elite,
+            ));
+            delay += self.spacing * (count - 1) as f32 + self.group_gap;
+            elite = false;
+        }
+
+        if let Some(boss) = boss {
+            let spawner = pick_spawner(spawners, rng);
+            groups.push(new_group(&boss, 1, spawner, self.spacing, delay, false));
+        }
+
+        WaveDefinition {
+            build_time: Some(self.build_time),
+            groups,
+        }
+    }
+}
+
+fn is_every(wave_number: usize, interval: usize) -> bool {
+    interval > 0 && wave_number % interval == 0
+}
+
+fn pick_spawner<R: Rng>(spawners: &[SpawnerId], rng: &mut R) -> SpawnerId {
+    spawners.choose(rng).cloned().unwrap_or_default()
+}
+
+fn new_group(
+    enemy: &EnemyType,
+    count: u32,
+    spawner: SpawnerId,
+    spacing: f32,
+    delay: f32,
+    elite: bool,
+) -> WaveGroup {
+    WaveGroup {
+        enemy: enemy.clone(),
+        count,
+        spacing,
+        delay,
+        spawner,
+        elite,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enemy::EnemyDefinition, seed::GameSeed, sprite::AssetType};
+
+    fn enemy(wave_cost: u32, boss: bool) -> EnemyDefinition {
+        EnemyDefinition {
+            max_health: 10,
+            speed: 30.0,
+            bounty: 1,
+            lives_cost: 1,
+            sprite_sheet: AssetType::JumpingJelly,
+            sprite_number: 0,
+            animation: None,
+            size: 16.0,
+            tint: None,
+            wave_cost: Some(wave_cost),
+            boss,
+        }
+    }
+
+    fn enemy_catalog() -> EnemyCatalog {
+        let mut enemy_catalog = EnemyCatalog::default();
+        for (name, wave_cost, boss) in
+            [("small", 2, false), ("large", 5, false), ("boss", 40, true)].iter()
+        {
+            enemy_catalog
+                .enemies
+                .insert(EnemyType(name.to_string()), enemy(*wave_cost, *boss));
+        }
+        enemy_catalog
+    }
+
+    fn endless_config() -> EndlessConfig {
+        EndlessConfig {
+            build_time: 10.0,
+            budget: BudgetCurve::Exponential {
+                base: 30.0,
+                growth: 1.15,
+            },
+            max_group_size: 12,
+            spacing: 1.0,
+            group_gap: 3.0,
+            elite_interval: 3,
+            elite_cost_multiplier: 3.0,
+            boss_interval: 10,
+        }
+    }
+
+    const SPAWNERS: [SpawnerId; 3] = [SpawnerId(0), SpawnerId(1), SpawnerId(2)];
+
+    fn generate(seed: u64, wave_number: usize) -> WaveDefinition {
+        endless_config().generate_wave(
+            wave_number,
+            &enemy_catalog(),
+            &SPAWNERS,
+            &mut GameSeed::new(seed).wave_rng(wave_number),
+        )
+    }
+
+    #[test]
+    fn same_seed_and_wave_number_give_the_same_wave() {
+        for wave_number in 1..=20 {
+            assert_eq!(generate(7, wave_number), generate(7, wave_number));
+        }
+    }
+
+    #[test]
+    fn wave_number_changes_the_wave() {
+        assert_ne!(generate(7, 4), generate(7, 5));
+    }
+
+    #[test]
+    fn groups_come_from_the_level_spawners() {
+        let spawners = (1..=20)
+            .flat_map(|wave_number| generate(7, wave_number).groups)
+            .map(|group| group.spawner)
+            .collect::<Vec<_>>();
+        assert!(spawners.iter().all(|spawner| SPAWNERS.contains(spawner)));
+        assert!(SPAWNERS.iter().all(|spawner| spawners.contains(spawner)));
+    }
+}
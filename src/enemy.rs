@@ -1,30 +1,108 @@
+use std::collections::BTreeMap;
+
 use amethyst::{
     core::{math::Vector3, transform::Transform},
     ecs::prelude::{
-        Component, DenseVecStorage, Entities, Join, LazyUpdate, ReadExpect, System, Write,
-        WriteStorage,
+        Component, DenseVecStorage, Entities, Join, LazyUpdate, ReadExpect, ReadStorage, System,
+        Write, WriteExpect, WriteStorage,
     },
-    renderer::SpriteRender,
-    ui::UiText,
+    renderer::{palette::Srgba, resources::Tint, SpriteRender},
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    sprite::{AssetType, SpriteSheetMap},
-    velocity::Velocity,
-    BuildingMaterials, GameUi,
+    path::PathFollower,
+    sprite::{AssetType, SpriteAnimation, SpriteAnimationDefinition, SpriteSheetMap},
+    status_effect::StatusEffects,
+    BuildingMaterials, PlayerLives,
 };
 
 #[derive(Component)]
 pub struct Enemy {
     pub health: i32,
+    pub max_health: i32,
     pub value: i32,
+    // Lives lost when this enemy reaches the end of the path
+    pub damage: i32,
+    pub size: f32,
     pub enemy_type: EnemyType,
 }
 
-#[derive(Clone, Copy)]
-pub enum EnemyType {
-    JumpingJelly,
-    SlideySlime,
+/** The id an enemy is listed under in `enemies.ron` */
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct EnemyType(pub String);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EnemyDefinition {
+    pub max_health: i32,
+    // Distance travelled along the path every second
+    pub speed: f32,
+    // Coins awarded for killing the enemy
+    pub bounty: i32,
+    // Lives lost if the enemy reaches the end of the path
+    pub lives_cost: i32,
+    pub sprite_sheet: AssetType,
+    #[serde(default)]
+    pub sprite_number: usize,
+    #[serde(default)]
+    pub animation: Option<SpriteAnimationDefinition>,
+    // Diameter of the enemy in world units
+    pub size: f32,
+    #[serde(default)]
+    pub tint: Option<[f32; 4]>,
+    // Points it takes out of an endless wave's budget. Enemies without one are
+    // never picked by the wave generator.
+    #[serde(default)]
+    pub wave_cost: Option<u32>,
+    // Bosses are only picked by the wave generator for boss waves
+    #[serde(default)]
+    pub boss: bool,
+}
+
+/** How an elite differs from the regular version of the same enemy */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EliteModifiers {
+    pub health_multiplier: f32,
+    pub bounty_multiplier: f32,
+    #[serde(default)]
+    pub tint: Option<[f32; 4]>,
+}
+
+impl Default for EliteModifiers {
+    fn default() -> Self {
+        EliteModifiers {
+            health_multiplier: 2.0,
+            bounty_multiplier: 2.0,
+            tint: None,
+        }
+    }
+}
+
+impl EliteModifiers {
+    pub fn apply(&self, definition: &EnemyDefinition) -> EnemyDefinition {
+        EnemyDefinition {
+            max_health: (definition.max_health as f32 * self.health_multiplier) as i32,
+            bounty: (definition.bounty as f32 * self.bounty_multiplier) as i32,
+            tint: self.tint.or(definition.tint),
+            ..definition.clone()
+        }
+    }
+}
+
+/** Every enemy the game knows about, loaded from `assets/enemies.ron` */
+#[derive(Default, Deserialize, Serialize)]
+pub struct EnemyCatalog {
+    // A BTreeMap keeps the iteration order stable so seeded runs stay reproducible
+    pub enemies: BTreeMap<EnemyType, EnemyDefinition>,
+    #[serde(default)]
+    pub elite: EliteModifiers,
+}
+
+impl EnemyCatalog {
+    pub fn get(&self, enemy_type: &EnemyType) -> Option<&EnemyDefinition> {
+        self.enemies.get(enemy_type)
+    }
 }
 
 pub struct EnemySystem;
@@ -34,18 +112,38 @@ impl<'s> System<'s> for EnemySystem {
         Entities<'s>,
         WriteStorage<'s, Enemy>,
         Write<'s, BuildingMaterials>,
-        WriteStorage<'s, UiText>,
-        ReadExpect<'s, GameUi>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, mut enemies, mut building_materials, mut ui_text, game_ui) = data;
+        let (entities, mut enemies, mut building_materials) = data;
         for (entity, enemy) in (&entities, &mut enemies).join() {
             if enemy.health <= 0 {
                 building_materials.coins += enemy.value;
-                if let Some(text) = ui_text.get_mut(game_ui.coin_display) {
-                    text.text = format!("Coins: {}", building_materials.coins.to_string());
-                }
+                let _ = entities.delete(entity);
+            }
+        }
+    }
+}
+
+// Removes enemies that made it to the end of their path and takes their
+// damage off the player's lives.
+pub struct LeakSystem;
+
+impl<'s> System<'s> for LeakSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Enemy>,
+        ReadStorage<'s, PathFollower>,
+        WriteExpect<'s, PlayerLives>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, enemies, path_followers, mut player_lives) = data;
+        for (entity, enemy, path_follower) in (&entities, &enemies, &path_followers).join() {
+            // Enemies killed on their last step are paid out by `EnemySystem`
+            // instead
+            if path_follower.is_finished() && enemy.health > 0 {
+                player_lives.lives -= enemy.damage;
                 let _ = entities.delete(entity);
             }
         }
@@ -57,31 +155,42 @@ pub fn create_enemy(
     lazy_update: &ReadExpect<LazyUpdate>,
     sprite_sheet_map: SpriteSheetMap,
     origin: Vector3<f32>,
+    waypoints: Vec<Vector3<f32>>,
     enemy_type: EnemyType,
+    definition: &EnemyDefinition,
 ) {
-    let asset_type = match enemy_type {
-        EnemyType::JumpingJelly => AssetType::JumpingJelly,
-        EnemyType::SlideySlime => AssetType::SlideySlime,
-    };
     let enemy_sprite = SpriteRender {
-        sprite_sheet: sprite_sheet_map.get(asset_type).unwrap().clone(),
-        sprite_number: 0,
+        sprite_sheet: sprite_sheet_map
+            .get(definition.sprite_sheet)
+            .unwrap()
+            .clone(),
+        sprite_number: definition.sprite_number,
     };
 
     let mut transform = Transform::default();
     transform.set_translation(origin);
 
-    let velocity = Velocity::new(Vector3::new(0.25, 0.0, 0.0));
+    let path_follower = PathFollower::new(waypoints, definition.speed);
 
     let enemy = Enemy {
-        health: 100,
-        value: 10,
+        health: definition.max_health,
+        max_health: definition.max_health,
+        value: definition.bounty,
+        damage: definition.lives_cost,
+        size: definition.size,
         enemy_type,
     };
 
     let entity = entities.create();
     lazy_update.insert(entity, enemy_sprite);
     lazy_update.insert(entity, transform);
-    lazy_update.insert(entity, velocity);
+    lazy_update.insert(entity, path_follower);
     lazy_update.insert(entity, enemy);
+    lazy_update.insert(entity, StatusEffects::default());
+    if let Some(animation) = &definition.animation {
+        lazy_update.insert(entity, SpriteAnimation::new(animation.clone()));
+    }
+    if let Some([r, g, b, a]) = definition.tint {
+        lazy_update.insert(entity, Tint(Srgba::new(r, g, b, a)));
+    }
 }
@@ -0,0 +1,55 @@
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    prelude::*,
+    ui::{get_default_font, Anchor, FontAsset, UiText, UiTransform},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    // The player ran out of lives
+    Defeat,
+    // The player survived every wave
+    Victory,
+}
+
+pub struct GameOverState {
+    pub outcome: Outcome,
+}
+
+impl SimpleState for GameOverState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+
+        // Clear out the map, towers and remaining enemies
+        world.delete_all();
+
+        let font_handle = {
+            let loader = world.read_resource::<Loader>();
+            let font_store = world.read_resource::<AssetStorage<FontAsset>>();
+            get_default_font(&loader, &font_store)
+        };
+
+        let game_over_transform = UiTransform::new(
+            "game_over_transform".to_string(),
+            Anchor::Middle,
+            Anchor::Middle,
+            0.,
+            0.,
+            1.,
+            400.,
+            100.,
+        );
+
+        let message = match self.outcome {
+            Outcome::Defeat => "Game Over",
+            Outcome::Victory => "Victory!",
+        };
+        let game_over_text = UiText::new(font_handle, message.to_string(), [1., 1., 1., 1.], 50.);
+
+        world
+            .create_entity()
+            .with(game_over_transform)
+            .with(game_over_text)
+            .build();
+    }
+}
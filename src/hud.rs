@@ -0,0 +1,122 @@
+use amethyst::{
+    core::Hidden,
+    ecs::prelude::{Read, ReadExpect, ReadStorage, System, WriteStorage},
+    input::{InputHandler, StringBindings},
+    ui::UiText,
+};
+
+use crate::{
+    selection::{hotkey_label, TowerSelection, SELL_ACTION, UPGRADE_ACTIONS},
+    tower::{Tower, TowerCatalog},
+    wave::{WavePhase, WaveState},
+    BuildingMaterials, GameUi, PlayerLives,
+};
+
+pub struct HudSystem;
+
+impl<'s> System<'s> for HudSystem {
+    type SystemData = (
+        WriteStorage<'s, UiText>,
+        ReadExpect<'s, GameUi>,
+        Read<'s, BuildingMaterials>,
+        ReadExpect<'s, PlayerLives>,
+        ReadExpect<'s, WaveState>,
+        Read<'s, TowerSelection>,
+        ReadStorage<'s, Tower>,
+        ReadExpect<'s, TowerCatalog>,
+        WriteStorage<'s, Hidden>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut ui_text,
+            game_ui,
+            building_materials,
+            player_lives,
+            wave_state,
+            tower_selection,
+            towers,
+            tower_catalog,
+            mut hidden,
+            input,
+        ) = data;
+        if let Some(text) = ui_text.get_mut(game_ui.coin_display) {
+            text.text = format!("Coins: {}", building_materials.coins);
+        }
+        if let Some(text) = ui_text.get_mut(game_ui.lives_display) {
+            text.text = format!("Lives: {}", player_lives.lives.max(0));
+        }
+        if let Some(text) = ui_text.get_mut(game_ui.wave_display) {
+            let wave = match wave_state.total_waves {
+                Some(total_waves) => format!("Wave {}/{}", wave_state.wave_number, total_waves),
+                None => format!("Wave {}", wave_state.wave_number),
+            };
+            text.text = match wave_state.phase {
+                WavePhase::Build { time_remaining } => {
+                    format!("{} in {}s", wave, time_remaining.ceil())
+                }
+                WavePhase::Active => format!("{}: {} left", wave, wave_state.enemies_remaining),
+                WavePhase::Cleared | WavePhase::Victory => format!("{} cleared", wave),
+            };
+        }
+        let selected_tower = tower_selection.tower.and_then(|tower| towers.get(tower));
+        if let Some(text) = ui_text.get_mut(game_ui.selection_display) {
+            text.text = match selected_tower {
+                Some(tower) => format!(
+                    "{} level {}: {:?}",
+                    tower.kind.0,
+                    tower.level + 1,
+                    tower.targeting
+                ),
+                None => String::new(),
+            };
+        }
+
+        let next_upgrades = selected_tower
+            .and_then(|tower| {
+                tower_catalog
+                    .get(&tower.kind)
+                    .map(|definition| definition.upgrades.next_upgrades(tower.level, tower.branch))
+            })
+            .unwrap_or_default();
+        match selected_tower {
+            Some(tower) => {
+                hidden.remove(game_ui.sell_button);
+                if let Some(text) = ui_text.get_mut(game_ui.sell_button) {
+                    let sell_value = tower.sell_value(tower_catalog.sell_refund, &wave_state);
+                    let sell = format!("Sell ({})", sell_value);
+                    text.text = with_hotkey(hotkey_label(&input, SELL_ACTION), sell);
+                }
+            }
+            None => {
+                let _ = hidden.insert(game_ui.sell_button, Hidden);
+            }
+        }
+        for (index, button) in game_ui.upgrade_buttons.iter().enumerate() {
+            match next_upgrades.get(index) {
+                Some((_, upgrade)) => {
+                    hidden.remove(*button);
+                    if let Some(text) = ui_text.get_mut(*button) {
+                        let hotkey = UPGRADE_ACTIONS
+                            .get(index)
+                            .and_then(|action| hotkey_label(&input, action));
+                        let upgrade = format!("{} ({})", upgrade.name, upgrade.cost);
+                        text.text = with_hotkey(hotkey, upgrade);
+                    }
+                }
+                None => {
+                    let _ = hidden.insert(*button, Hidden);
+                }
+            }
+        }
+    }
+}
+
+// Prefixes a button's text with the key that does the same thing, if any
+fn with_hotkey(hotkey: Option<String>, text: String) -> String {
+    match hotkey {
+        Some(hotkey) => format!("{}: {}", hotkey, text),
+        None => text,
+    }
+}
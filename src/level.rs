@@ -0,0 +1,265 @@
+use std::{fs, path::Path};
+
+use amethyst::{config::Config, error::Error};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    map_generator::MapGenerator,
+    spawner::SpawnerId,
+    tile_map::{parse_map, EnemyPath, TileCatalog, TileMap, TileType},
+};
+
+const STARTING_LIVES: i32 = 20;
+const STARTING_COINS: i32 = 100;
+
+/** An enemy path and the spawner at its start */
+#[derive(Deserialize, Serialize)]
+pub struct SpawnPoint {
+    pub spawner: SpawnerId,
+    pub enemy_path: EnemyPath,
+}
+
+/** A map and everything needed to play it, either generated or loaded from a level file */
+#[derive(Deserialize, Serialize)]
+pub struct Level {
+    pub tile_map: TileMap,
+    pub spawn_points: Vec<SpawnPoint>,
+    // The tile every enemy path leads to
+    pub goal: (i32, i32),
+    pub starting_coins: i32,
+    pub starting_lives: i32,
+    // Wave list in `assets/` to play instead of `waves.ron`
+    #[serde(default)]
+    pub waves: Option<String>,
+}
+
+impl Level {
+    pub fn generate(
+        width: i32,
+        height: i32,
+        map_generator: &dyn MapGenerator,
+        rng: &mut dyn RngCore,
+    ) -> Result<Self, Error> {
+        let (tile_map, enemy_path) = map_generator.generate(width, height, rng)?;
+        Ok(Level::with_defaults(tile_map, enemy_path))
+    }
+
+    pub fn spawners(&self) -> Vec<SpawnerId> {
+        self.spawn_points
+            .iter()
+            .map(|spawn_point| spawn_point.spawner)
+            .collect()
+    }
+
+    // A level for a map with a single path, using the default coins, lives
+    // and waves
+    fn with_defaults(tile_map: TileMap, enemy_path: EnemyPath) -> Self {
+        let goal = *enemy_path.coords().last().unwrap();
+        Level {
+            tile_map,
+            spawn_points: vec![SpawnPoint {
+                spawner: SpawnerId(0),
+                enemy_path,
+            }],
+            goal,
+            starting_coins: STARTING_COINS,
+            starting_lives: STARTING_LIVES,
+            waves: None,
+        }
+    }
+
+    // Loads a RON level file, or a `.txt` map drawn the way `TileMap`'s
+    // `Display` prints maps and read back with `parse_map`
+    pub fn from_file<P: AsRef<Path>>(path: P, tile_catalog: &TileCatalog) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let level = if path
+            .extension()
+            .map_or(false, |extension| extension == "txt")
+        {
+            let (tile_map, enemy_path) = parse_map(&fs::read_to_string(path)?)?;
+            Level::with_defaults(tile_map, enemy_path)
+        } else {
+            Level::load_no_fallback(path)?
+        };
+        level.validate(tile_catalog)?;
+        Ok(level)
+    }
+
+    // Hand-authored levels can be wrong in ways the file format can't catch
+    fn validate(&self, tile_catalog: &TileCatalog) -> Result<(), Error> {
+        let tile_map = &self.tile_map;
+        let expected_tiles = (tile_map.width * tile_map.height) as usize;
+        if tile_map.tiles.len() != expected_tiles {
+            return Err(Error::from_string(format!(
+                "Level has {} tiles, a {}x{} map needs {}",
+                tile_map.tiles.len(),
+                tile_map.width,
+                tile_map.height,
+                expected_tiles
+            )));
+        }
+        if tile_map.get(self.goal) != Some(TileType::Goal) {
+            return Err(Error::from_string(format!(
+                "Level goal {:?} isn't on a Goal tile",
+                self.goal
+            )));
+        }
+        if self.spawn_points.is_empty() {
+            return Err(Error::from_string("Level has no spawn points"));
+        }
+        for spawn_point in self.spawn_points.iter() {
+            let coords = spawn_point.enemy_path.coords();
+            if let Some(coord) = coords.iter().find(|&&coord| !tile_map.is_within(coord)) {
+                return Err(Error::from_string(format!(
+                    "Path of spawner {} leaves the map at {:?}",
+                    spawn_point.spawner.0, coord
+                )));
+            }
+            if tile_map.get(coords[0]) != Some(TileType::Spawn) {
+                return Err(Error::from_string(format!(
+                    "Path of spawner {} starts at {:?}, which isn't a Spawn tile",
+                    spawn_point.spawner.0, coords[0]
+                )));
+            }
+            let blocked = coords.iter().find(|&&coord| {
+                !tile_map
+                    .get(coord)
+                    .map_or(false, |tile| tile_catalog.is_walkable(tile))
+            });
+            if let Some(coord) = blocked {
+                return Err(Error::from_string(format!(
+                    "Path of spawner {} crosses an unwalkable {:?} tile at {:?}",
+                    spawn_point.spawner.0,
+                    tile_map.get(*coord).unwrap(),
+                    coord
+                )));
+            }
+            // A tower could be built in the way of the enemies
+            let buildable = coords.iter().find(|&&coord| {
+                tile_map
+                    .get(coord)
+                    .map_or(false, |tile| tile_catalog.is_buildable(tile))
+            });
+            if let Some(coord) = buildable {
+                return Err(Error::from_string(format!(
+                    "Path of spawner {} crosses a buildable {:?} tile at {:?}",
+                    spawn_point.spawner.0,
+                    tile_map.get(*coord).unwrap(),
+                    coord
+                )));
+            }
+            if coords.last() != Some(&self.goal) {
+                return Err(Error::from_string(format!(
+                    "Path of spawner {} ends at {:?} instead of the goal {:?}",
+                    spawn_point.spawner.0,
+                    coords.last().unwrap(),
+                    self.goal
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile_map::TileDefinition;
+
+    fn tile_catalog() -> TileCatalog {
+        let mut tile_catalog = TileCatalog::default();
+        for &(tile, buildable, walkable) in [
+            (TileType::Grass, true, false),
+            (TileType::Path, false, true),
+            (TileType::Rock, false, false),
+            (TileType::Water, false, false),
+            (TileType::Bridge, false, true),
+            (TileType::Spawn, false, true),
+            (TileType::Goal, false, true),
+        ]
+        .iter()
+        {
+            tile_catalog.tiles.insert(
+                tile,
+                TileDefinition {
+                    sprite_number: 0,
+                    overlay_sprite_number: None,
+                    buildable,
+                    walkable,
+                },
+            );
+        }
+        tile_catalog
+    }
+
+    fn level() -> Level {
+        let (tile_map, enemy_path) = parse_map(
+            "\
+.......
+S##=#..
+..~~#..
+....##E
+",
+        )
+        .unwrap();
+        Level::with_defaults(tile_map, enemy_path)
+    }
+
+    fn validate_error(level: &Level) -> String {
+        validate_error_with(level, &tile_catalog())
+    }
+
+    fn validate_error_with(level: &Level, tile_catalog: &TileCatalog) -> String {
+        level.validate(tile_catalog).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn accepts_a_valid_level() {
+        assert!(level().validate(&tile_catalog()).is_ok());
+    }
+
+    #[test]
+    fn paths_start_on_a_spawn() {
+        let mut level = level();
+        level.tile_map.set((0, 2), TileType::Path);
+        assert!(validate_error(&level)
+            .contains("Path of spawner 0 starts at (0, 2), which isn't a Spawn tile"));
+    }
+
+    #[test]
+    fn goal_is_on_a_goal_tile() {
+        let mut level = level();
+        level.goal = (5, 0);
+        assert!(validate_error(&level).contains("Level goal (5, 0) isn't on a Goal tile"));
+        level.goal = (20, 20);
+        assert!(validate_error(&level).contains("Level goal (20, 20) isn't on a Goal tile"));
+    }
+
+    #[test]
+    fn paths_stay_off_buildable_tiles() {
+        let mut level = level();
+        level.tile_map.set((1, 2), TileType::Grass);
+        assert!(validate_error(&level)
+            .contains("Path of spawner 0 crosses an unwalkable Grass tile at (1, 2)"));
+
+        // Tiles can't be both walked and built on
+        let mut tile_catalog = tile_catalog();
+        tile_catalog
+            .tiles
+            .get_mut(&TileType::Grass)
+            .unwrap()
+            .walkable = true;
+        assert!(validate_error_with(&level, &tile_catalog)
+            .contains("Path of spawner 0 crosses a buildable Grass tile at (1, 2)"));
+    }
+
+    #[test]
+    fn paths_end_at_the_goal() {
+        let mut level = level();
+        level.tile_map.set((6, 3), TileType::Goal);
+        level.goal = (6, 3);
+        assert!(validate_error(&level)
+            .contains("Path of spawner 0 ends at (6, 0) instead of the goal (6, 3)"));
+    }
+}
@@ -1,13 +1,28 @@
 #[macro_use]
 extern crate specs_derive;
 
+mod autotile;
+mod cleanup;
+mod cli;
+mod endless;
 mod enemy;
+mod game_over;
+mod hud;
+mod level;
+mod map_generator;
+mod path;
+mod placement;
 mod projectile;
+mod seed;
+mod selection;
+mod spatial_grid;
 mod spawner;
 mod sprite;
+mod status_effect;
 mod tile_map;
 mod tower;
 mod velocity;
+mod wave;
 
 use std::time::Duration;
 
@@ -17,48 +32,71 @@ use amethyst::{
         AnimationSetPrefab, EndControl,
     },
     assets::{
-        AssetStorage, Handle, Loader, PrefabData, PrefabLoader, PrefabLoaderSystemDesc,
-        ProgressCounter, RonFormat,
+        AssetStorage, Loader, PrefabData, PrefabLoader, PrefabLoaderSystemDesc, ProgressCounter,
+        RonFormat,
     },
+    config::Config,
     core::{
         ecs::Entity,
         frame_limiter::FrameRateLimitStrategy,
-        math::Vector3,
         transform::{Transform, TransformBundle},
+        ArcThreadPool, Hidden,
     },
     derive::PrefabData,
-    ecs::{Entities, Join, ReadStorage, WriteStorage},
+    ecs::{Dispatcher, DispatcherBuilder, Entities, Join, ReadStorage, WriteStorage},
     error::Error,
     input::{InputBundle, StringBindings},
     prelude::*,
     renderer::{
         plugins::{RenderFlat2D, RenderToWindow},
-        sprite::{prefab::SpriteScenePrefab, SpriteRender, SpriteSheet},
+        sprite::{prefab::SpriteScenePrefab, SpriteRender},
         types::DefaultBackend,
         Camera, RenderingBundle,
     },
-    ui::{get_default_font, Anchor, FontAsset, RenderUi, UiBundle, UiImage, UiText, UiTransform},
+    ui::{
+        get_default_font, Anchor, FontAsset, Interactable, RenderUi, UiBundle, UiImage, UiText,
+        UiTransform,
+    },
     utils::application_root_dir,
 };
-use rand::Rng;
+use log::info;
 use serde::Deserialize;
 
 use crate::{
-    enemy::EnemySystem,
-    projectile::ProjectileSystem,
-    spawner::{create_spawner, SpawnerSystem},
-    sprite::{AssetType, SpriteSheetMap},
-    tile_map::{generate_map, TileMap, TileType},
-    tower::{create_tower, TowerSystem},
+    autotile::{AutotileRules, AutotileSystem},
+    cleanup::CleanupSystem,
+    cli::CliArgs,
+    endless::EndlessConfig,
+    enemy::{EnemyCatalog, EnemySystem, LeakSystem},
+    game_over::{GameOverState, Outcome},
+    hud::HudSystem,
+    level::Level,
+    map_generator::MapConfig,
+    path::PathFollowerSystem,
+    placement::TowerPlacementSystem,
+    projectile::{FlightSystem, HomingSystem, ProjectileSystem},
+    seed::GameSeed,
+    selection::TowerSelectionSystem,
+    spatial_grid::SpatialGridSystem,
+    spawner::create_spawner,
+    sprite::{AssetType, SpriteAnimationSystem, SpriteSheetMap},
+    status_effect::StatusEffectSystem,
+    tile_map::{TileCatalog, TILE_SIZE},
+    tower::{TowerCatalog, TowerSystem},
     velocity::VelocitySystem,
+    wave::{WaveList, WavePhase, WaveSource, WaveState, WaveSystem},
 };
 
-const MIN_PATH_LENGTH: usize = 80;
+const SIMULATION_STEPS_PER_SECOND: u32 = 60;
 
 #[derive(Default)]
 struct GameplayState {
+    // The level to play, taken when the state starts
+    level: Option<Level>,
     // A progress tracker to check that assets are loaded
     pub progress_counter: Option<ProgressCounter>,
+    // Runs the gameplay systems on a fixed timestep
+    simulation_dispatcher: Option<Dispatcher<'static, 'static>>,
 }
 
 // Loading data for one entity
@@ -74,40 +112,15 @@ impl SimpleState for GameplayState {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
         let world = data.world;
 
-        /* Generate map */
+        let level = self.level.take().expect("GameplayState needs a level");
         let sprite_sheet_map = SpriteSheetMap::new(world);
-        let floor_tiles = sprite_sheet_map.get(AssetType::Floor).unwrap();
-
-        let x_tile_count = (SCREEN_WIDTH / 16.0) as i32;
-        let y_tile_count = (SCREEN_HEIGHT / 16.0) as i32;
 
-        // Reroll the map until the path is at least 80 tiles long
-        let (tile_map, enemy_path) = {
-            let mut tile_map_tuple = generate_map(x_tile_count, y_tile_count);
-            while tile_map_tuple.1.path.len() < MIN_PATH_LENGTH {
-                tile_map_tuple = generate_map(x_tile_count, y_tile_count);
-            }
-            tile_map_tuple
-        };
-
-        /* Enemy Spawner */
-        let spawner_position = Vector3::new(
-            (enemy_path.starting_coord.0 as f32) * 16.0 + 8.0,
-            (enemy_path.starting_coord.1 as f32) * 16.0 + 8.0,
-            0.0,
-        );
-        create_spawner(world, spawner_position);
-
-        let mut rng = rand::thread_rng();
-        for _ in 0..16 {
-            let x = rng.gen::<i32>().abs() % x_tile_count;
-            let y = rng.gen::<i32>().abs() % y_tile_count;
-            println!("{}, {}", x, y);
-            if tile_map.get((x, y)) != Some(TileType::Rock) {
-                let tower_pos = Vector3::new((x as f32) * 16.0 + 8.0, (y as f32) * 16.0 + 8.0, 0.0);
-                create_tower(world, floor_tiles.clone(), tower_pos);
-            }
+        /* Enemy Spawners */
+        for spawn_point in level.spawn_points.iter() {
+            create_spawner(world, spawn_point.spawner, &spawn_point.enemy_path);
         }
+        let wave_state = WaveState::new(&world.read_resource::<WaveSource>());
+        world.insert(wave_state);
 
         /* Initialize animated entities */
         // Create new progress counter
@@ -121,11 +134,16 @@ impl SimpleState for GameplayState {
             )
         });
 
-        init_floor_tiles(world, floor_tiles.clone(), &tile_map);
+        world.insert(PlayerLives {
+            lives: level.starting_lives,
+        });
+        world.insert(BuildingMaterials {
+            coins: level.starting_coins,
+        });
         init_ui(world);
         init_camera(world);
 
-        world.insert(tile_map);
+        world.insert(level.tile_map);
         world.insert(sprite_sheet_map);
         // Create one set of entities from the prefab.
         (0..1).for_each(|_| {
@@ -134,6 +152,17 @@ impl SimpleState for GameplayState {
                 .with(jumping_jelly_prefab.clone())
                 .build();
         });
+
+        self.simulation_dispatcher = Some(build_simulation_dispatcher(world));
+    }
+
+    fn fixed_update(&mut self, data: StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        if let Some(dispatcher) = self.simulation_dispatcher.as_mut() {
+            dispatcher.dispatch(&data.world);
+            // Apply entity creation and deletion before the next step
+            data.world.maintain();
+        }
+        Trans::None
     }
 
     fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
@@ -168,6 +197,17 @@ impl SimpleState for GameplayState {
                 self.progress_counter = None;
             }
         }
+
+        if data.world.read_resource::<PlayerLives>().lives <= 0 {
+            return Trans::Switch(Box::new(GameOverState {
+                outcome: Outcome::Defeat,
+            }));
+        }
+        if data.world.read_resource::<WaveState>().phase == WavePhase::Victory {
+            return Trans::Switch(Box::new(GameOverState {
+                outcome: Outcome::Victory,
+            }));
+        }
         Trans::None
     }
 }
@@ -175,10 +215,59 @@ impl SimpleState for GameplayState {
 fn main() -> amethyst::Result<()> {
     amethyst::start_logger(Default::default());
 
+    let cli_args = CliArgs::parse()?;
+    let seed = cli_args.seed.unwrap_or_else(rand::random);
+    info!("Seed: {}", seed);
+    let mut game_seed = GameSeed::new(seed);
+
     let app_root = application_root_dir()?;
 
+    let assets_dir = app_root.join("assets");
     let config_dir = app_root.join("config");
     let display_config_path = config_dir.join("display.ron");
+    let bindings_config_path = config_dir.join("bindings.ron");
+
+    let tile_catalog = TileCatalog::load_no_fallback(assets_dir.join("tiles.ron"))?;
+    let autotile_rules = AutotileRules::load_no_fallback(assets_dir.join("autotile.ron"))?;
+    let level = match &cli_args.level {
+        Some(level_path) => Level::from_file(level_path, &tile_catalog)?,
+        None => {
+            let map_config_path = match &cli_args.map_config {
+                Some(map_config_path) => map_config_path.clone(),
+                None => assets_dir.join("map.ron"),
+            };
+            let mut map_config = MapConfig::load_no_fallback(map_config_path)?;
+            if let Some(generator) = cli_args.map_generator {
+                map_config.generator = generator;
+            }
+            let x_tile_count = (SCREEN_WIDTH / TILE_SIZE) as i32;
+            let y_tile_count = (SCREEN_HEIGHT / TILE_SIZE) as i32;
+            Level::generate(
+                x_tile_count,
+                y_tile_count,
+                map_config.generator(),
+                &mut game_seed.rng,
+            )?
+        }
+    };
+    info!("Map:\n{}", level.tile_map);
+    if let Some(export_path) = &cli_args.export_level {
+        level.write(export_path)?;
+        info!("Level saved to {}", export_path.display());
+    }
+
+    let enemy_catalog = EnemyCatalog::load_no_fallback(assets_dir.join("enemies.ron"))?;
+    let tower_catalog = TowerCatalog::load_no_fallback(assets_dir.join("towers.ron"))?;
+    let wave_source = if cli_args.endless {
+        WaveSource::Endless(EndlessConfig::load_no_fallback(
+            assets_dir.join("endless.ron"),
+        )?)
+    } else {
+        let waves = level.waves.as_ref().map_or("waves.ron", String::as_str);
+        let wave_list = WaveList::load_no_fallback(assets_dir.join(waves))?;
+        wave_list.validate(&enemy_catalog, &level.spawners())?;
+        WaveSource::Scripted(wave_list)
+    };
 
     let rendering_bundle = RenderingBundle::<DefaultBackend>::new()
         .with_plugin(RenderFlat2D::default())
@@ -188,7 +277,8 @@ fn main() -> amethyst::Result<()> {
                 .with_clear([0.34, 0.36, 0.52, 1.0]),
         );
 
-    let input_bundle = InputBundle::<StringBindings>::new();
+    let input_bundle =
+        InputBundle::<StringBindings>::new().with_bindings_from_file(bindings_config_path)?;
 
     let game_data = GameDataBuilder::default()
         .with_system_desc(
@@ -207,13 +297,32 @@ fn main() -> amethyst::Result<()> {
         )?
         .with_bundle(UiBundle::<StringBindings>::new())?
         .with_bundle(input_bundle)?
-        .with(VelocitySystem, "velocity_system", &[])
-        .with(TowerSystem, "tower_system", &[])
-        .with(EnemySystem, "enemy_system", &[])
-        .with(ProjectileSystem, "projectile_system", &[])
-        .with(SpawnerSystem, "spawner_system", &[]);
+        .with(SpriteAnimationSystem, "sprite_animation_system", &[])
+        .with(AutotileSystem::default(), "autotile_system", &[])
+        .with(
+            TowerPlacementSystem::default(),
+            "tower_placement_system",
+            &["input_system"],
+        )
+        .with(
+            TowerSelectionSystem::default(),
+            "tower_selection_system",
+            &["tower_placement_system"],
+        )
+        .with(HudSystem, "hud_system", &["tower_selection_system"]);
 
-    let mut game = Application::build("assets/", GameplayState::default())?
+    let gameplay_state = GameplayState {
+        level: Some(level),
+        ..Default::default()
+    };
+    let mut game = Application::build("assets/", gameplay_state)?
+        .with_resource(game_seed)
+        .with_resource(tile_catalog)
+        .with_resource(autotile_rules)
+        .with_resource(enemy_catalog)
+        .with_resource(tower_catalog)
+        .with_resource(wave_source)
+        .with_fixed_step_length(Duration::from_secs(1) / SIMULATION_STEPS_PER_SECOND)
         .with_frame_limit(
             FrameRateLimitStrategy::SleepAndYield(Duration::from_millis(2)),
             60,
@@ -224,6 +333,45 @@ fn main() -> amethyst::Result<()> {
     Ok(())
 }
 
+// Gameplay systems run from `GameplayState::fixed_update` rather than with the
+// rest of the game data, so the outcome of a game doesn't depend on the render
+// frame rate. They measure time with `Time::fixed_seconds`.
+fn build_simulation_dispatcher(world: &mut World) -> Dispatcher<'static, 'static> {
+    let mut dispatcher = DispatcherBuilder::new()
+        .with(HomingSystem, "homing_system", &[])
+        .with(VelocitySystem, "velocity_system", &["homing_system"])
+        .with(StatusEffectSystem, "status_effect_system", &[])
+        .with(
+            PathFollowerSystem,
+            "path_follower_system",
+            &["status_effect_system"],
+        )
+        .with(
+            SpatialGridSystem,
+            "spatial_grid_system",
+            &["velocity_system", "path_follower_system"],
+        )
+        .with(TowerSystem, "tower_system", &["spatial_grid_system"])
+        .with(EnemySystem, "enemy_system", &["status_effect_system"])
+        .with(LeakSystem, "leak_system", &["path_follower_system"])
+        .with(FlightSystem, "flight_system", &[])
+        .with(
+            ProjectileSystem,
+            "projectile_system",
+            &["flight_system", "spatial_grid_system"],
+        )
+        .with(
+            CleanupSystem,
+            "cleanup_system",
+            &["velocity_system", "projectile_system"],
+        )
+        .with(WaveSystem, "wave_system", &["enemy_system", "leak_system"])
+        .with_pool((*world.read_resource::<ArcThreadPool>()).clone())
+        .build();
+    dispatcher.setup(world);
+    dispatcher
+}
+
 pub const SCREEN_WIDTH: f32 = 320.0;
 pub const SCREEN_HEIGHT: f32 = 240.0;
 
@@ -243,8 +391,18 @@ pub struct BuildingMaterials {
     coins: i32,
 }
 
+pub struct PlayerLives {
+    lives: i32,
+}
+
 pub struct GameUi {
     pub coin_display: Entity,
+    pub lives_display: Entity,
+    pub wave_display: Entity,
+    pub selection_display: Entity,
+    // Buy the selected tower's next upgrades, in the order of the upgrade hotkeys
+    pub upgrade_buttons: Vec<Entity>,
+    pub sell_button: Entity,
 }
 
 fn init_ui(world: &mut World) {
@@ -267,7 +425,10 @@ fn init_ui(world: &mut World) {
 
     let coin_display_text = UiText::new(
         font_handle.clone(),
-        "Coins: 0".to_string(),
+        format!(
+            "Coins: {}",
+            world.read_resource::<BuildingMaterials>().coins
+        ),
         [1., 1., 1., 1.],
         20.,
     );
@@ -281,32 +442,142 @@ fn init_ui(world: &mut World) {
         .with(coin_display_background)
         .build();
 
-    world.insert(GameUi { coin_display });
-}
+    let lives_display_transform = UiTransform::new(
+        "lives_display_transform".to_string(),
+        Anchor::TopRight,
+        Anchor::TopRight,
+        0.,
+        0.,
+        1.,
+        200.,
+        50.,
+    );
 
-fn init_floor_tiles(world: &mut World, sprite_sheet: Handle<SpriteSheet>, tile_map: &TileMap) {
-    for x in 0..tile_map.width {
-        for y in 0..tile_map.height {
-            let tile = tile_map
-                .get((x, y))
-                .expect(format!("No tile at location ({}, {})", x, y).as_str());
-            let sprite_number = match tile {
-                TileType::Grass => 4,
-                TileType::Rock => 2,
-            };
-            let sprite_render = SpriteRender {
-                sprite_sheet: sprite_sheet.clone(),
-                sprite_number: sprite_number,
-            };
+    let lives_display_text = UiText::new(
+        font_handle.clone(),
+        format!("Lives: {}", world.read_resource::<PlayerLives>().lives),
+        [1., 1., 1., 1.],
+        20.,
+    );
+
+    let lives_display_background = UiImage::SolidColor([0.2, 0.2, 0.2, 0.5]);
+
+    let lives_display = world
+        .create_entity()
+        .with(lives_display_transform)
+        .with(lives_display_text)
+        .with(lives_display_background)
+        .build();
+
+    let wave_display_transform = UiTransform::new(
+        "wave_display_transform".to_string(),
+        Anchor::TopMiddle,
+        Anchor::TopMiddle,
+        0.,
+        0.,
+        1.,
+        240.,
+        50.,
+    );
+
+    let wave_display_text = UiText::new(
+        font_handle.clone(),
+        "Wave 1".to_string(),
+        [1., 1., 1., 1.],
+        20.,
+    );
+
+    let wave_display_background = UiImage::SolidColor([0.2, 0.2, 0.2, 0.5]);
+
+    let wave_display = world
+        .create_entity()
+        .with(wave_display_transform)
+        .with(wave_display_text)
+        .with(wave_display_background)
+        .build();
 
-            let mut transform = Transform::default();
-            transform.set_translation_xyz(((x as f32) * 16.) + 8., ((y as f32) * 16.) + 8., -1.);
+    let selection_display_transform = UiTransform::new(
+        "selection_display_transform".to_string(),
+        Anchor::BottomMiddle,
+        Anchor::BottomMiddle,
+        0.,
+        0.,
+        1.,
+        400.,
+        50.,
+    );
+
+    // Empty until a tower is selected
+    let selection_display_text =
+        UiText::new(font_handle.clone(), String::new(), [1., 1., 1., 1.], 20.);
+
+    let selection_display = world
+        .create_entity()
+        .with(selection_display_transform)
+        .with(selection_display_text)
+        .build();
+
+    // Hidden until a tower with upgrades left is selected
+    let upgrade_buttons = (0..2)
+        .map(|index| {
+            let upgrade_button_transform = UiTransform::new(
+                format!("upgrade_button_{}_transform", index + 1),
+                Anchor::BottomMiddle,
+                Anchor::BottomMiddle,
+                (index as f32 - 0.5) * 200.,
+                50.,
+                1.,
+                190.,
+                30.,
+            );
+
+            let upgrade_button_text =
+                UiText::new(font_handle.clone(), String::new(), [1., 1., 1., 1.], 14.);
+
+            let upgrade_button_background = UiImage::SolidColor([0.2, 0.2, 0.2, 0.8]);
 
             world
                 .create_entity()
-                .with(transform)
-                .with(sprite_render)
-                .build();
-        }
-    }
+                .with(upgrade_button_transform)
+                .with(upgrade_button_text)
+                .with(upgrade_button_background)
+                .with(Interactable)
+                .with(Hidden)
+                .build()
+        })
+        .collect();
+
+    // Hidden until a tower is selected
+    let sell_button_transform = UiTransform::new(
+        "sell_button_transform".to_string(),
+        Anchor::BottomMiddle,
+        Anchor::BottomMiddle,
+        0.,
+        85.,
+        1.,
+        190.,
+        30.,
+    );
+
+    let sell_button_text = UiText::new(font_handle.clone(), String::new(), [1., 1., 1., 1.], 14.);
+
+    let sell_button_background = UiImage::SolidColor([0.4, 0.15, 0.15, 0.8]);
+
+    let sell_button = world
+        .create_entity()
+        .with(sell_button_transform)
+        .with(sell_button_text)
+        .with(sell_button_background)
+        .with(Interactable)
+        .with(Hidden)
+        .build();
+
+    world.insert(GameUi {
+        coin_display,
+        lives_display,
+        wave_display,
+        selection_display,
+        upgrade_buttons,
+        sell_button,
+    });
 }
@@ -0,0 +1,741 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    str::FromStr,
+};
+
+use amethyst::error::Error;
+use rand::{seq::SliceRandom, Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::tile_map::{
+    direction_to_coord, Direction, EnemyPath, TileMap, TileType, ALL_DIRECTIONS,
+};
+
+// Tries a generator gets to meet its path requirements before giving up
+const MAX_ATTEMPTS: u32 = 1000;
+
+// Terrain scattered over maps whose generator only lays out a path
+const MAX_PONDS: u32 = 4;
+const MAX_POND_RADIUS: i32 = 2;
+const ROCK_CHANCE: f32 = 0.04;
+
+/** Lays out a map and the path enemies walk across it */
+pub trait MapGenerator {
+    fn path_requirements(&self) -> &PathRequirements;
+
+    // One try at a map. None if the try went nowhere, the map is then
+    // rerolled. An error means the generator itself is broken.
+    fn try_generate(
+        &self,
+        width: i32,
+        height: i32,
+        rng: &mut dyn RngCore,
+    ) -> Result<Option<(TileMap, EnemyPath)>, Error>;
+
+    // Reroll the map until its path meets the requirements
+    fn generate(
+        &self,
+        width: i32,
+        height: i32,
+        rng: &mut dyn RngCore,
+    ) -> Result<(TileMap, EnemyPath), Error> {
+        for _ in 0..MAX_ATTEMPTS {
+            if let Some((tile_map, enemy_path)) = self.try_generate(width, height, rng)? {
+                if self.path_requirements().are_met(&tile_map, &enemy_path) {
+                    return Ok((tile_map, enemy_path));
+                }
+            }
+        }
+        Err(Error::from_string(format!(
+            "No map met the path requirements in {} attempts",
+            MAX_ATTEMPTS
+        )))
+    }
+}
+
+/** What a generated path has to look like, whichever generator made it */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PathRequirements {
+    // Lengths count the steps between tiles
+    pub min_length: usize,
+    #[serde(default)]
+    pub max_length: Option<usize>,
+    // Start and end the path on the edge of the map
+    #[serde(default)]
+    pub edge_to_edge: bool,
+}
+
+impl PathRequirements {
+    fn are_met(&self, tile_map: &TileMap, enemy_path: &EnemyPath) -> bool {
+        let length = enemy_path.path.len();
+        let coords = enemy_path.coords();
+        let ends_on_edges =
+            is_on_edge(tile_map, coords[0]) && is_on_edge(tile_map, coords[coords.len() - 1]);
+        length >= self.min_length
+            && self
+                .max_length
+                .map_or(true, |max_length| length <= max_length)
+            && (ends_on_edges || !self.edge_to_edge)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum GeneratorKind {
+    RandomWalk,
+    Serpentine,
+    NoisePath,
+    CellularAutomata,
+}
+
+// The names `--map-generator` takes
+impl FromStr for GeneratorKind {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name {
+            "random-walk" => Ok(GeneratorKind::RandomWalk),
+            "serpentine" => Ok(GeneratorKind::Serpentine),
+            "noise-path" => Ok(GeneratorKind::NoisePath),
+            "cellular-automata" => Ok(GeneratorKind::CellularAutomata),
+            _ => Err(Error::from_string(format!(
+                "Unknown map generator: {}",
+                name
+            ))),
+        }
+    }
+}
+
+/** Settings for every map generator and which one to use, loaded from `assets/map.ron` */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MapConfig {
+    pub generator: GeneratorKind,
+    pub random_walk: RandomWalk,
+    pub serpentine: Serpentine,
+    pub noise_path: NoisePath,
+    pub cellular_automata: CellularAutomata,
+}
+
+impl MapConfig {
+    pub fn generator(&self) -> &dyn MapGenerator {
+        match self.generator {
+            GeneratorKind::RandomWalk => &self.random_walk,
+            GeneratorKind::Serpentine => &self.serpentine,
+            GeneratorKind::NoisePath => &self.noise_path,
+            GeneratorKind::CellularAutomata => &self.cellular_automata,
+        }
+    }
+}
+
+/** Wanders from a random tile, turning at random, until it boxes itself in */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RandomWalk {
+    pub path: PathRequirements,
+    // Chance of carrying on in the same direction when that is possible
+    pub straight_chance: f32,
+}
+
+impl MapGenerator for RandomWalk {
+    fn path_requirements(&self) -> &PathRequirements {
+        &self.path
+    }
+
+    fn try_generate(
+        &self,
+        width: i32,
+        height: i32,
+        rng: &mut dyn RngCore,
+    ) -> Result<Option<(TileMap, EnemyPath)>, Error> {
+        let mut tile_map = TileMap::new(width, height, TileType::Grass);
+        let mut path = vec![];
+        let starting_coord = if self.path.edge_to_edge {
+            random_edge_coord(width, height, rng)
+        } else {
+            (rng.gen_range(0, width), rng.gen_range(0, height))
+        };
+        let mut cursor_coord = starting_coord;
+        let mut current_direction = *ALL_DIRECTIONS.choose(rng).unwrap();
+        // place first tile
+        tile_map.set(cursor_coord, TileType::Path);
+
+        let mut reached_end = false;
+        while !reached_end {
+            // Filter out the directions we can't move in
+            let valid_directions = ALL_DIRECTIONS
+                .iter()
+                .filter(|&dir| {
+                    let direction_coord = direction_to_coord(*dir);
+                    let test_coord = (
+                        cursor_coord.0 + direction_coord.0,
+                        cursor_coord.1 + direction_coord.1,
+                    );
+                    // Make sure we aren't overlapping a previous part of the path
+                    let is_not_path = tile_map
+                        .get(test_coord)
+                        .map_or(false, |tile| tile != TileType::Path);
+                    // Make sure we aren't touching a previous part of the path
+                    let is_not_path_adjacent = tile_map
+                        .get_neighbors(test_coord)
+                        .iter()
+                        .filter(|&tile| *tile == TileType::Path)
+                        .count()
+                        <= 1;
+                    is_not_path && is_not_path_adjacent
+                })
+                .collect::<Vec<_>>();
+
+            let can_move_forward = valid_directions.contains(&&current_direction);
+            let r = rng.gen::<f32>();
+            if can_move_forward && r < self.straight_chance {
+                // Keep moving forward
+                add_path_tile(
+                    &current_direction,
+                    &mut cursor_coord,
+                    &mut path,
+                    &mut tile_map,
+                );
+            } else {
+                // Pivot to a random (valid) direction
+                match valid_directions.choose(rng) {
+                    Some(&random_direction) => {
+                        current_direction = *random_direction;
+                        add_path_tile(
+                            &current_direction,
+                            &mut cursor_coord,
+                            &mut path,
+                            &mut tile_map,
+                        );
+                    }
+                    None => {
+                        // No more valid directions; end the path
+                        reached_end = true;
+                    }
+                }
+            }
+
+            // Stop at the first edge the path reaches once it is long enough
+            let is_long_enough = path.len() >= self.path.min_length;
+            if self.path.edge_to_edge && is_long_enough && is_on_edge(&tile_map, cursor_coord) {
+                reached_end = true;
+            }
+            if self.path.max_length == Some(path.len()) {
+                reached_end = true;
+            }
+        }
+        tile_map.set(cursor_coord, TileType::Goal);
+        tile_map.set(starting_coord, TileType::Spawn);
+        scatter_terrain(&mut tile_map, rng);
+        Ok(Some((
+            tile_map,
+            EnemyPath {
+                starting_coord,
+                path,
+            },
+        )))
+    }
+}
+
+fn add_path_tile(
+    direction: &Direction,
+    cursor_coord: &mut (i32, i32),
+    path: &mut Vec<Direction>,
+    tile_map: &mut TileMap,
+) {
+    let movement_coord = direction_to_coord(*direction);
+    *cursor_coord = (
+        (*cursor_coord).0 + movement_coord.0,
+        (*cursor_coord).1 + movement_coord.1,
+    );
+    path.push(*direction);
+    tile_map.set(*cursor_coord, TileType::Path);
+}
+
+/** Sweeps back and forth across the map in lanes, like a farmer ploughing a field */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Serpentine {
+    pub path: PathRequirements,
+    // Rows from one lane to the next, at least 2 so lanes don't touch
+    pub lane_spacing: i32,
+    // Most tiles a lane can turn short of the side of the map
+    pub turn_jitter: i32,
+}
+
+impl MapGenerator for Serpentine {
+    fn path_requirements(&self) -> &PathRequirements {
+        &self.path
+    }
+
+    fn try_generate(
+        &self,
+        width: i32,
+        height: i32,
+        rng: &mut dyn RngCore,
+    ) -> Result<Option<(TileMap, EnemyPath)>, Error> {
+        let lane_spacing = self.lane_spacing.max(2);
+        // Keep lanes from overlapping however short they turn
+        let turn_jitter = self.turn_jitter.max(0).min(width / 3);
+        // Lanes keep a tile away from the sides, except where the path enters
+        // and leaves the map
+        let (inner_left, inner_right) = (1, width - 2);
+        let (outer_left, outer_right) = if self.path.edge_to_edge {
+            (0, width - 1)
+        } else {
+            (inner_left, inner_right)
+        };
+
+        let mut coords = vec![(outer_left, height - 2)];
+        let mut going_right = true;
+        // The most steps one lane across the map can take
+        let lane_length = (width - 1) as usize;
+        loop {
+            let (x, y) = coords[coords.len() - 1];
+            // Run the last lane out to the edge rather than past the longest
+            // allowed path, even if there is room for more lanes
+            let next_lane_fits = self.path.max_length.map_or(true, |max_length| {
+                coords.len() - 1 + 2 * lane_length + lane_spacing as usize <= max_length
+            });
+            let is_last_lane = y - lane_spacing < 1 || !next_lane_fits;
+            let turn_x = match (going_right, is_last_lane) {
+                (true, true) => outer_right,
+                (false, true) => outer_left,
+                (true, false) => inner_right - rng.gen_range(0, turn_jitter + 1),
+                (false, false) => inner_left + rng.gen_range(0, turn_jitter + 1),
+            };
+            let step = if going_right { 1 } else { -1 };
+            coords.extend((1..=(turn_x - x) * step).map(|offset| (x + offset * step, y)));
+            if is_last_lane {
+                break;
+            }
+            coords.extend((1..=lane_spacing).map(|offset| (turn_x, y - offset)));
+            going_right = !going_right;
+        }
+
+        // Start from any corner
+        let flip_x = rng.gen::<bool>();
+        let flip_y = rng.gen::<bool>();
+        for coord in coords.iter_mut() {
+            if flip_x {
+                coord.0 = width - 1 - coord.0;
+            }
+            if flip_y {
+                coord.1 = height - 1 - coord.1;
+            }
+        }
+
+        let mut tile_map = TileMap::new(width, height, TileType::Grass);
+        lay_path(&mut tile_map, &coords);
+        scatter_terrain(&mut tile_map, rng);
+        Ok(Some((tile_map, EnemyPath::from_coords(&coords)?)))
+    }
+}
+
+/** Raises hills and lakes from smooth noise, then finds the easiest way between two edges of the map */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NoisePath {
+    pub path: PathRequirements,
+    // Tiles between the random points the noise is smoothed between
+    pub noise_scale: f32,
+    // Noise above this level is rock
+    pub rock_level: f32,
+    // Noise below this level is water
+    pub water_level: f32,
+    // How much harder a tile is to cross for every step up in noise, which
+    // makes the path wind through valleys
+    pub hill_cost: f32,
+    // How many grass tiles a bridge is worth avoiding
+    pub water_cost: u32,
+}
+
+impl MapGenerator for NoisePath {
+    fn path_requirements(&self) -> &PathRequirements {
+        &self.path
+    }
+
+    fn try_generate(
+        &self,
+        width: i32,
+        height: i32,
+        rng: &mut dyn RngCore,
+    ) -> Result<Option<(TileMap, EnemyPath)>, Error> {
+        let noise = value_noise(width, height, self.noise_scale, rng);
+        let mut tile_map = TileMap::new(width, height, TileType::Grass);
+        for (tile, &level) in tile_map.tiles.iter_mut().zip(noise.iter()) {
+            if level > self.rock_level {
+                *tile = TileType::Rock;
+            } else if level < self.water_level {
+                *tile = TileType::Water;
+            }
+        }
+
+        let (start, goal) = opposite_edge_coords(width, height, rng);
+        tile_map.set(start, TileType::Grass);
+        tile_map.set(goal, TileType::Grass);
+        let coords = find_path(&tile_map, start, goal, |coord, tile| match tile {
+            TileType::Rock => None,
+            TileType::Water => Some(self.water_cost),
+            _ => {
+                let level = noise[(coord.0 + coord.1 * width) as usize];
+                Some(1 + (level * self.hill_cost) as u32)
+            }
+        });
+        let coords = match coords {
+            Some(coords) => coords,
+            None => return Ok(None),
+        };
+
+        lay_path(&mut tile_map, &coords);
+        Ok(Some((tile_map, EnemyPath::from_coords(&coords)?)))
+    }
+}
+
+/** Grows rock and water from random seeds into natural looking clumps, then finds a way between two edges of the map */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CellularAutomata {
+    pub path: PathRequirements,
+    // Share of tiles that start out as rock and water
+    pub rock_chance: f32,
+    pub water_chance: f32,
+    // Rounds of turning tiles mostly surrounded by rock or water into it
+    pub smoothing_steps: u32,
+    // How many grass tiles a bridge is worth avoiding
+    pub water_cost: u32,
+}
+
+impl MapGenerator for CellularAutomata {
+    fn path_requirements(&self) -> &PathRequirements {
+        &self.path
+    }
+
+    fn try_generate(
+        &self,
+        width: i32,
+        height: i32,
+        rng: &mut dyn RngCore,
+    ) -> Result<Option<(TileMap, EnemyPath)>, Error> {
+        let rock = cellular_layer(width, height, self.rock_chance, self.smoothing_steps, rng);
+        let water = cellular_layer(width, height, self.water_chance, self.smoothing_steps, rng);
+        let mut tile_map = TileMap::new(width, height, TileType::Grass);
+        for (index, tile) in tile_map.tiles.iter_mut().enumerate() {
+            if rock[index] {
+                *tile = TileType::Rock;
+            } else if water[index] {
+                *tile = TileType::Water;
+            }
+        }
+
+        let (start, goal) = opposite_edge_coords(width, height, rng);
+        tile_map.set(start, TileType::Grass);
+        tile_map.set(goal, TileType::Grass);
+        let coords = find_path(&tile_map, start, goal, |_, tile| match tile {
+            TileType::Rock => None,
+            TileType::Water => Some(self.water_cost),
+            _ => Some(1),
+        });
+        let coords = match coords {
+            Some(coords) => coords,
+            None => return Ok(None),
+        };
+
+        lay_path(&mut tile_map, &coords);
+        Ok(Some((tile_map, EnemyPath::from_coords(&coords)?)))
+    }
+}
+
+fn is_on_edge(tile_map: &TileMap, coord: (i32, i32)) -> bool {
+    coord.0 == 0 || coord.1 == 0 || coord.0 == tile_map.width - 1 || coord.1 == tile_map.height - 1
+}
+
+fn random_edge_coord<R: Rng + ?Sized>(width: i32, height: i32, rng: &mut R) -> (i32, i32) {
+    match rng.gen_range(0, 4) {
+        0 => (0, rng.gen_range(0, height)),
+        1 => (width - 1, rng.gen_range(0, height)),
+        2 => (rng.gen_range(0, width), 0),
+        _ => (rng.gen_range(0, width), height - 1),
+    }
+}
+
+// A tile on one edge of the map and another on the opposite edge
+fn opposite_edge_coords<R: Rng + ?Sized>(
+    width: i32,
+    height: i32,
+    rng: &mut R,
+) -> ((i32, i32), (i32, i32)) {
+    let (start, goal) = if rng.gen::<bool>() {
+        (
+            (0, rng.gen_range(0, height)),
+            (width - 1, rng.gen_range(0, height)),
+        )
+    } else {
+        (
+            (rng.gen_range(0, width), 0),
+            (rng.gen_range(0, width), height - 1),
+        )
+    };
+    if rng.gen::<bool>() {
+        (goal, start)
+    } else {
+        (start, goal)
+    }
+}
+
+// Mark the tiles of a path, bridging any water it crosses
+fn lay_path(tile_map: &mut TileMap, coords: &[(i32, i32)]) {
+    for &coord in coords.iter() {
+        let tile = match tile_map.get(coord) {
+            Some(TileType::Water) => TileType::Bridge,
+            _ => TileType::Path,
+        };
+        tile_map.set(coord, tile);
+    }
+    tile_map.set(coords[coords.len() - 1], TileType::Goal);
+    tile_map.set(coords[0], TileType::Spawn);
+}
+
+// Drop a few ponds and rocks around the path. Where a pond covers the path
+// the path crosses it on a bridge.
+fn scatter_terrain<R: Rng + ?Sized>(tile_map: &mut TileMap, rng: &mut R) {
+    for _ in 0..rng.gen_range(1, MAX_PONDS + 1) {
+        let center = (
+            rng.gen_range(0, tile_map.width),
+            rng.gen_range(0, tile_map.height),
+        );
+        let radius = rng.gen_range(1, MAX_POND_RADIUS + 1);
+        for x in (center.0 - radius)..=(center.0 + radius) {
+            for y in (center.1 - radius)..=(center.1 + radius) {
+                // Round off the corners
+                let (dx, dy) = (x - center.0, y - center.1);
+                if dx * dx + dy * dy > radius * radius {
+                    continue;
+                }
+                match tile_map.get((x, y)) {
+                    Some(TileType::Grass) => tile_map.set((x, y), TileType::Water),
+                    Some(TileType::Path) => tile_map.set((x, y), TileType::Bridge),
+                    _ => None,
+                };
+            }
+        }
+    }
+    for tile in tile_map.tiles.iter_mut() {
+        if *tile == TileType::Grass && rng.gen::<f32>() < ROCK_CHANCE {
+            *tile = TileType::Rock;
+        }
+    }
+}
+
+// The cheapest path from `start` to `goal` with A*. `cost` is what stepping
+// onto a tile costs, None for tiles that can't be crossed. Costs must be at
+// least 1, which also keeps the path from touching itself: a tile next to an
+// earlier part of the path would have been a shortcut.
+fn find_path<F>(
+    tile_map: &TileMap,
+    start: (i32, i32),
+    goal: (i32, i32),
+    cost: F,
+) -> Option<Vec<(i32, i32)>>
+where
+    F: Fn((i32, i32), TileType) -> Option<u32>,
+{
+    let distance = |coord: (i32, i32)| ((coord.0 - goal.0).abs() + (coord.1 - goal.1).abs()) as u32;
+    let mut open = BinaryHeap::new();
+    let mut best_costs = HashMap::new();
+    let mut came_from = HashMap::new();
+    open.push(Reverse((distance(start), start)));
+    best_costs.insert(start, 0);
+
+    while let Some(Reverse((_, coord))) = open.pop() {
+        if coord == goal {
+            let mut coords = vec![goal];
+            while let Some(&previous) = came_from.get(&coords[coords.len() - 1]) {
+                coords.push(previous);
+            }
+            coords.reverse();
+            return Some(coords);
+        }
+        let coord_cost = best_costs[&coord];
+        for direction in ALL_DIRECTIONS.iter() {
+            let movement_coord = direction_to_coord(*direction);
+            let next_coord = (coord.0 + movement_coord.0, coord.1 + movement_coord.1);
+            let step_cost = match tile_map
+                .get(next_coord)
+                .and_then(|tile| cost(next_coord, tile))
+            {
+                Some(step_cost) => step_cost.max(1),
+                None => continue,
+            };
+            let next_cost = coord_cost + step_cost;
+            if best_costs
+                .get(&next_coord)
+                .map_or(true, |&best| next_cost < best)
+            {
+                best_costs.insert(next_coord, next_cost);
+                came_from.insert(next_coord, coord);
+                open.push(Reverse((next_cost + distance(next_coord), next_coord)));
+            }
+        }
+    }
+    None
+}
+
+// Smooth noise between 0 and 1, one value per tile in the map's tile order
+fn value_noise<R: Rng + ?Sized>(width: i32, height: i32, scale: f32, rng: &mut R) -> Vec<f32> {
+    let scale = scale.max(1.0);
+    let lattice_width = (width as f32 / scale).ceil() as usize + 2;
+    let lattice_height = (height as f32 / scale).ceil() as usize + 2;
+    let lattice = (0..lattice_width * lattice_height)
+        .map(|_| rng.gen::<f32>())
+        .collect::<Vec<_>>();
+    let lattice_value = |x: usize, y: usize| lattice[x + y * lattice_width];
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+
+    let mut noise = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (lattice_x, lattice_y) = (x as f32 / scale, y as f32 / scale);
+            let (x0, y0) = (lattice_x.floor() as usize, lattice_y.floor() as usize);
+            let (tx, ty) = (smooth(lattice_x.fract()), smooth(lattice_y.fract()));
+            let bottom = lattice_value(x0, y0) * (1.0 - tx) + lattice_value(x0 + 1, y0) * tx;
+            let top = lattice_value(x0, y0 + 1) * (1.0 - tx) + lattice_value(x0 + 1, y0 + 1) * tx;
+            noise.push(bottom * (1.0 - ty) + top * ty);
+        }
+    }
+    noise
+}
+
+// Random cells smoothed by the usual cave rule: a cell fills if at least five
+// of its eight neighbors are filled and stays filled with four. Cells off the
+// map count as empty so the edges stay open.
+fn cellular_layer<R: Rng + ?Sized>(
+    width: i32,
+    height: i32,
+    fill_chance: f32,
+    steps: u32,
+    rng: &mut R,
+) -> Vec<bool> {
+    let mut cells = (0..width * height)
+        .map(|_| rng.gen::<f32>() < fill_chance)
+        .collect::<Vec<_>>();
+    for _ in 0..steps {
+        let previous = cells.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let mut filled_neighbors = 0;
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let (nx, ny) = (x + dx, y + dy);
+                        let is_within = nx >= 0 && nx < width && ny >= 0 && ny < height;
+                        if (dx, dy) != (0, 0) && is_within && previous[(nx + ny * width) as usize] {
+                            filled_neighbors += 1;
+                        }
+                    }
+                }
+                let index = (x + y * width) as usize;
+                cells[index] = filled_neighbors >= 5 || (previous[index] && filled_neighbors >= 4);
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::config::Config;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::tile_map::TileCatalog;
+
+    // The size of the map the game generates
+    const WIDTH: i32 = 20;
+    const HEIGHT: i32 = 15;
+
+    fn asset<C: Config>(name: &str) -> C {
+        C::load_no_fallback(format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+    }
+
+    fn assert_playable(map_generator: &dyn MapGenerator, seed: u64) {
+        let tile_catalog = asset::<TileCatalog>("tiles.ron");
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (tile_map, enemy_path) = map_generator.generate(WIDTH, HEIGHT, &mut rng).unwrap();
+        let coords = enemy_path.coords();
+
+        for (index, &coord) in coords.iter().enumerate() {
+            assert!(
+                tile_map
+                    .get(coord)
+                    .map_or(false, |tile| tile_catalog.is_walkable(tile)),
+                "seed {}: path crosses {:?} at {:?}",
+                seed,
+                tile_map.get(coord),
+                coord
+            );
+            // Tiles further along the path can't be next to this one or on
+            // it, or the path would touch itself
+            for &later in coords.iter().skip(index + 2) {
+                let distance = (coord.0 - later.0).abs() + (coord.1 - later.1).abs();
+                assert!(
+                    distance > 1,
+                    "seed {}: path touches itself at {:?} and {:?}",
+                    seed,
+                    coord,
+                    later
+                );
+            }
+        }
+        for step in coords.windows(2) {
+            let distance = (step[0].0 - step[1].0).abs() + (step[0].1 - step[1].1).abs();
+            assert_eq!(
+                distance, 1,
+                "seed {}: path jumps from {:?} to {:?}",
+                seed, step[0], step[1]
+            );
+        }
+        assert_eq!(tile_map.get(coords[0]), Some(TileType::Spawn));
+        assert_eq!(tile_map.get(coords[coords.len() - 1]), Some(TileType::Goal));
+
+        let requirements = map_generator.path_requirements();
+        assert!(enemy_path.path.len() >= requirements.min_length);
+        if let Some(max_length) = requirements.max_length {
+            assert!(enemy_path.path.len() <= max_length);
+        }
+        if requirements.edge_to_edge {
+            assert!(is_on_edge(&tile_map, coords[0]));
+            assert!(is_on_edge(&tile_map, coords[coords.len() - 1]));
+        }
+    }
+
+    #[test]
+    fn every_generator_makes_playable_paths() {
+        let mut map_config = asset::<MapConfig>("map.ron");
+        for &generator in [
+            GeneratorKind::RandomWalk,
+            GeneratorKind::Serpentine,
+            GeneratorKind::NoisePath,
+            GeneratorKind::CellularAutomata,
+        ]
+        .iter()
+        {
+            map_config.generator = generator;
+            for seed in 0..10 {
+                assert_playable(map_config.generator(), seed);
+            }
+        }
+    }
+
+    #[test]
+    fn short_serpentines_still_reach_the_edge() {
+        // Too short for every lane, so the path has to stop partway down the
+        // map on a lane that runs out to the edge
+        let serpentine = Serpentine {
+            path: PathRequirements {
+                min_length: 20,
+                max_length: Some(45),
+                edge_to_edge: true,
+            },
+            lane_spacing: 3,
+            turn_jitter: 3,
+        };
+        for seed in 0..10 {
+            assert_playable(&serpentine, seed);
+        }
+    }
+}
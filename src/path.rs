@@ -0,0 +1,92 @@
+use std::f32::EPSILON;
+
+use amethyst::{
+    core::{math::Vector3, timing::Time, transform::Transform},
+    ecs::prelude::{Component, DenseVecStorage, Join, Read, ReadStorage, System, WriteStorage},
+};
+
+use crate::status_effect::StatusEffects;
+
+#[derive(Component)]
+pub struct PathFollower {
+    waypoints: Vec<Vector3<f32>>,
+    next_waypoint: usize,
+    // In units per second
+    pub speed: f32,
+    // How far along its path the follower has moved so far
+    pub distance_travelled: f32,
+}
+
+impl PathFollower {
+    pub fn new(waypoints: Vec<Vector3<f32>>, speed: f32) -> Self {
+        PathFollower {
+            waypoints,
+            // The first waypoint is where the follower is spawned
+            next_waypoint: 1,
+            speed,
+            distance_travelled: 0.0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_waypoint >= self.waypoints.len()
+    }
+
+    // Where the follower will be after moving `distance` further along its
+    // path from `position`
+    pub fn position_after(&self, position: &Vector3<f32>, distance: f32) -> Vector3<f32> {
+        let mut position = *position;
+        let mut remaining = distance;
+        for waypoint in self.waypoints.iter().skip(self.next_waypoint) {
+            let to_waypoint = waypoint - position;
+            let waypoint_distance = to_waypoint.norm();
+            if remaining <= waypoint_distance {
+                return position + to_waypoint * (remaining / waypoint_distance.max(EPSILON));
+            }
+            position = *waypoint;
+            remaining -= waypoint_distance;
+        }
+        position
+    }
+}
+
+pub struct PathFollowerSystem;
+
+impl<'s> System<'s> for PathFollowerSystem {
+    type SystemData = (
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, PathFollower>,
+        ReadStorage<'s, StatusEffects>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut transforms, mut followers, status_effects, time) = data;
+        for (transform, follower, status_effects) in
+            (&mut transforms, &mut followers, status_effects.maybe()).join()
+        {
+            let speed_multiplier = status_effects.map_or(1.0, |effects| effects.speed_multiplier());
+            // Any distance left over after reaching a waypoint carries on
+            // towards the next one, so corners don't slow the follower down.
+            let mut remaining = follower.speed * speed_multiplier * time.fixed_seconds();
+            while remaining > 0.0 {
+                let waypoint = match follower.waypoints.get(follower.next_waypoint) {
+                    Some(waypoint) => *waypoint,
+                    None => break,
+                };
+                let to_waypoint = waypoint - transform.translation();
+                let distance = to_waypoint.norm();
+                if distance <= remaining {
+                    transform.set_translation(waypoint);
+                    follower.next_waypoint += 1;
+                    follower.distance_travelled += distance;
+                    remaining -= distance;
+                } else {
+                    transform.append_translation(to_waypoint * (remaining / distance));
+                    follower.distance_travelled += remaining;
+                    remaining = 0.0;
+                }
+            }
+        }
+    }
+}
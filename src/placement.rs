@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use amethyst::{
+    core::{geometry::Plane, math::Point2, transform::Transform, Hidden},
+    ecs::prelude::{
+        Entities, Entity, Join, LazyUpdate, Read, ReadExpect, ReadStorage, System, Write,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::Camera,
+    ui::{Interactable, UiTransform},
+    window::ScreenDimensions,
+};
+
+use crate::{
+    selection::TowerSelection,
+    spawner::Spawner,
+    sprite::SpriteSheetMap,
+    tile_map::{tile_center, world_to_tile, TileCatalog, TileMap},
+    tower::{create_tower, TowerCatalog, TowerKind},
+    wave::{WavePhase, WaveState},
+    BuildingMaterials,
+};
+
+// Tracks which tiles already have a tower built on them
+#[derive(Default)]
+pub struct TileOccupancy {
+    towers: HashMap<(i32, i32), Entity>,
+}
+
+impl TileOccupancy {
+    pub fn is_occupied(&self, coord: (i32, i32)) -> bool {
+        self.towers.contains_key(&coord)
+    }
+
+    pub fn get(&self, coord: (i32, i32)) -> Option<Entity> {
+        self.towers.get(&coord).cloned()
+    }
+
+    pub fn insert(&mut self, coord: (i32, i32), tower: Entity) {
+        self.towers.insert(coord, tower);
+    }
+
+    // Frees the tile a tower was built on
+    pub fn remove_tower(&mut self, tower: Entity) {
+        self.towers.retain(|_, occupant| *occupant != tower);
+    }
+}
+
+// The tower kind that will be built on the next click
+#[derive(Default)]
+pub struct BuildSelection {
+    pub kind: Option<TowerKind>,
+}
+
+#[derive(Default)]
+pub struct TowerPlacementSystem {
+    // Towers are placed when the button goes down, not while it is held
+    place_tower_was_down: bool,
+}
+
+impl<'s> System<'s> for TowerPlacementSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Camera>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, UiTransform>,
+        ReadStorage<'s, Interactable>,
+        ReadStorage<'s, Hidden>,
+        ReadStorage<'s, Spawner>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, ScreenDimensions>,
+        ReadExpect<'s, TileMap>,
+        ReadExpect<'s, TileCatalog>,
+        Write<'s, TileOccupancy>,
+        Write<'s, BuildingMaterials>,
+        Write<'s, BuildSelection>,
+        Write<'s, TowerSelection>,
+        ReadExpect<'s, TowerCatalog>,
+        ReadExpect<'s, WaveState>,
+        ReadExpect<'s, LazyUpdate>,
+        Read<'s, SpriteSheetMap>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            cameras,
+            transforms,
+            ui_transforms,
+            interactables,
+            hidden,
+            spawners,
+            input,
+            screen_dimensions,
+            tile_map,
+            tile_catalog,
+            mut tile_occupancy,
+            mut building_materials,
+            mut build_selection,
+            mut tower_selection,
+            tower_catalog,
+            wave_state,
+            lazy_update,
+            sprite_sheet_map,
+        ) = data;
+
+        // The number keys pick towers in the order of the catalog's build menu
+        for (index, kind) in tower_catalog.build_menu.iter().enumerate() {
+            let action = format!("build_tower_{}", index + 1);
+            if input.action_is_down(&action).unwrap_or(false) {
+                build_selection.kind = Some(kind.clone());
+            }
+        }
+        let kind = match build_selection
+            .kind
+            .clone()
+            .or_else(|| tower_catalog.build_menu.first().cloned())
+        {
+            Some(kind) => kind,
+            None => return,
+        };
+        let definition = match tower_catalog.get(&kind) {
+            Some(definition) => definition,
+            None => return,
+        };
+
+        let place_tower_is_down = input.action_is_down("place_tower").unwrap_or(false);
+        let clicked = place_tower_is_down && !self.place_tower_was_down;
+        self.place_tower_was_down = place_tower_is_down;
+        if !clicked {
+            return;
+        }
+
+        let mouse_position = match input.mouse_position() {
+            Some(mouse_position) => mouse_position,
+            None => return,
+        };
+        // Clicks on buttons are handled by the UI rather than the map. UI
+        // positions have their origin at the bottom of the screen.
+        let over_button =
+            (&ui_transforms, &interactables, !&hidden)
+                .join()
+                .any(|(ui_transform, _, _)| {
+                    ui_transform.position_inside(
+                        mouse_position.0,
+                        screen_dimensions.height() - mouse_position.1,
+                    )
+                });
+        if over_button {
+            return;
+        }
+        let world_position = match (&cameras, &transforms).join().next() {
+            Some((camera, camera_transform)) => {
+                // Project a ray from the camera onto the map plane
+                let ray = camera.projection().screen_ray(
+                    Point2::new(mouse_position.0, mouse_position.1),
+                    screen_dimensions.diagonal(),
+                    camera_transform,
+                );
+                let distance = match ray.intersect_plane(&Plane::with_z(0.0)) {
+                    Some(distance) => distance,
+                    None => return,
+                };
+                ray.at_distance(distance).coords
+            }
+            None => return,
+        };
+
+        let coord = world_to_tile(&world_position);
+        // Clicking a tower selects it, clicking anywhere else clears the selection
+        tower_selection.tower = tile_occupancy.get(coord);
+        let is_buildable = tile_map
+            .get(coord)
+            .map_or(false, |tile| tile_catalog.is_buildable(tile));
+        // Towers never block an enemy path, whatever the tile under it
+        let on_enemy_path = (&spawners).join().any(|spawner| spawner.is_on_path(coord));
+        if !is_buildable || on_enemy_path || tile_occupancy.is_occupied(coord) {
+            return;
+        }
+        if building_materials.coins < definition.cost {
+            return;
+        }
+
+        building_materials.coins -= definition.cost;
+        let built_before_wave = match wave_state.phase {
+            WavePhase::Build { .. } => Some(wave_state.wave_number),
+            _ => None,
+        };
+        let tower = create_tower(
+            &entities,
+            &lazy_update,
+            &sprite_sheet_map,
+            tile_center(coord),
+            kind,
+            definition,
+            built_before_wave,
+        );
+        tile_occupancy.insert(coord, tower);
+    }
+}
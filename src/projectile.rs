@@ -1,69 +1,423 @@
+use std::f32::consts::PI;
+
 use amethyst::{
     assets::Handle,
-    core::{math::Vector3, transform::Transform},
+    core::{math::Vector3, timing::Time, transform::Transform},
     ecs::prelude::{
-        Component, DenseVecStorage, Entities, Join, LazyUpdate, ReadExpect, ReadStorage, System,
-        WriteStorage,
+        Component, DenseVecStorage, Entities, Entity, Join, LazyUpdate, Read, ReadExpect,
+        ReadStorage, System, WriteStorage,
     },
     renderer::{SpriteRender, SpriteSheet},
 };
+use serde::{Deserialize, Serialize};
 
 use crate::enemy::Enemy;
+use crate::spatial_grid::{distance_sq, SpatialGrid};
+use crate::status_effect::{StatusEffect, StatusEffects};
+use crate::tower::TowerDefinition;
 use crate::velocity::Velocity;
 
+// Seconds before a projectile that hasn't hit anything is removed
+const PROJECTILE_LIFETIME: f32 = 5.0;
+// How far past its tower's range a projectile may travel
+const RANGE_MARGIN: f32 = 16.0;
+// How far a homing projectile looks for a new target when it loses its own
+const RETARGET_DISTANCE: f32 = 160.0;
+
 #[derive(Component)]
 pub struct Projectile {
-    // TODO: make this a vec
-    effect: ProjectileEffect,
+    // Applied in order when the projectile hits
+    effects: Vec<ProjectileEffect>,
+    origin: Vector3<f32>,
+    // Eased projectiles always land and homing ones chase their target, so
+    // they have no range limit
+    max_range: Option<f32>,
+    // Seconds left before the projectile is removed
+    lifetime: f32,
+    // Diameter in world units
+    size: f32,
+    // How many more enemies the projectile can hit before it is used up
+    hits_remaining: u32,
+    // Enemies already hit, a piercing projectile never hits one twice
+    hit_enemies: Vec<Entity>,
+}
+
+impl Projectile {
+    pub fn age(&mut self, seconds: f32) {
+        self.lifetime -= seconds;
+    }
+
+    // Whether the projectile has flown too far or for too long to hit anything
+    pub fn is_spent(&self, position: &Vector3<f32>) -> bool {
+        let out_of_range = self.max_range.map_or(false, |max_range| {
+            distance_sq(&self.origin, position) > max_range * max_range
+        });
+        out_of_range || self.lifetime <= 0.0
+    }
 }
 
-#[allow(dead_code)]
-enum TimingFunction {
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum TimingFunction {
     Linear, // bullet
     EaseIn, // rocket thruster
     EaseOut,
     EaseInOut, // trebuchet
 }
 
-#[allow(dead_code)]
-enum ProjectileEffect {
+impl TimingFunction {
+    // Map the fraction of the flight time that has passed to the fraction of
+    // the distance covered
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            TimingFunction::Linear => t,
+            TimingFunction::EaseIn => t * t,
+            TimingFunction::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            TimingFunction::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - 2.0 * (1.0 - t) * (1.0 - t)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Trajectory {
+    // Flies in a straight line at the tower's projectile speed until it hits
+    // an enemy
+    Straight,
+    // Flies to where the target is predicted to be after `flight_time`
+    // seconds and only hits once it lands. `arc_height` lifts the projectile
+    // visually during the flight, for trebuchet and mortar style shots.
+    Eased {
+        easing: TimingFunction,
+        flight_time: f32,
+        arc_height: f32,
+    },
+    // Steers towards the tower's target at up to `turn_rate` radians per
+    // second. Only hits that target unless `hits_others` is set.
+    Homing {
+        turn_rate: f32,
+        #[serde(default)]
+        on_target_lost: TargetLost,
+        #[serde(default)]
+        hits_others: bool,
+    },
+}
+
+impl Default for Trajectory {
+    fn default() -> Self {
+        Trajectory::Straight
+    }
+}
+
+// What a homing projectile does when its target dies or leaks
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum TargetLost {
+    // Disappear without hitting anything
+    Fizzle,
+    // Chase the closest remaining enemy
+    Retarget,
+}
+
+impl Default for TargetLost {
+    fn default() -> Self {
+        TargetLost::Fizzle
+    }
+}
+
+#[derive(Component)]
+pub struct Homing {
+    target: Entity,
+    // In radians per second
+    turn_rate: f32,
+    on_target_lost: TargetLost,
+    hits_others: bool,
+}
+
+impl Homing {
+    fn can_hit(&self, enemy: Entity) -> bool {
+        self.hits_others || self.target == enemy
+    }
+}
+
+// Turns homing projectiles towards their targets
+pub struct HomingSystem;
+
+impl<'s> System<'s> for HomingSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Homing>,
+        WriteStorage<'s, Velocity>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Enemy>,
+        Read<'s, SpatialGrid>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut homings, mut velocities, transforms, enemies, spatial_grid, time) = data;
+        for (entity, homing, velocity, transform) in
+            (&entities, &mut homings, &mut velocities, &transforms).join()
+        {
+            let position = transform.translation();
+            let target_is_alive = entities.is_alive(homing.target)
+                && enemies
+                    .get(homing.target)
+                    .map_or(false, |enemy| enemy.health > 0);
+            if !target_is_alive {
+                let closest = match homing.on_target_lost {
+                    TargetLost::Fizzle => None,
+                    TargetLost::Retarget => {
+                        spatial_grid.nearest(position, RETARGET_DISTANCE, |enemy_entity| {
+                            enemies
+                                .get(enemy_entity)
+                                .map_or(false, |enemy| enemy.health > 0)
+                        })
+                    }
+                };
+                match closest {
+                    Some(enemy_entity) => homing.target = enemy_entity,
+                    None => {
+                        let _ = entities.delete(entity);
+                        continue;
+                    }
+                }
+            }
+
+            let target_position = match transforms.get(homing.target) {
+                Some(target_transform) => target_transform.translation(),
+                None => continue,
+            };
+            let to_target = target_position - position;
+            if distance_sq(target_position, position) <= 0.0 {
+                continue;
+            }
+            let heading = velocity.vector.y.atan2(velocity.vector.x);
+            let mut turn = to_target.y.atan2(to_target.x) - heading;
+            // Turn whichever way round is shorter
+            if turn > PI {
+                turn -= 2.0 * PI;
+            } else if turn < -PI {
+                turn += 2.0 * PI;
+            }
+            let max_turn = homing.turn_rate * time.fixed_seconds();
+            let heading = heading + turn.max(-max_turn).min(max_turn);
+            let speed = velocity.vector.norm();
+            velocity.vector = Vector3::new(heading.cos() * speed, heading.sin() * speed, 0.0);
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Flight {
+    origin: Vector3<f32>,
+    target: Vector3<f32>,
+    easing: TimingFunction,
+    arc_height: f32,
+    // Both in seconds
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Flight {
+    fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            1.0
+        } else {
+            (self.elapsed / self.duration).min(1.0)
+        }
+    }
+
+    fn has_landed(&self) -> bool {
+        self.progress() >= 1.0
+    }
+}
+
+pub struct FlightSystem;
+
+impl<'s> System<'s> for FlightSystem {
+    type SystemData = (
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, Flight>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut transforms, mut flights, time) = data;
+        for (transform, flight) in (&mut transforms, &mut flights).join() {
+            flight.elapsed += time.fixed_seconds();
+            let t = flight.progress();
+            let mut position =
+                flight.origin + (flight.target - flight.origin) * flight.easing.apply(t);
+            // A parabola that is 0 at both ends of the flight and peaks halfway
+            position.y += flight.arc_height * 4.0 * t * (1.0 - t);
+            transform.set_translation(position);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ProjectileEffect {
     Damage(i32),
-    DoT(i32, i32),
-    Slow(i32, i32),
-    Stun(i32),
-    Area(i32),
-    Easing(TimingFunction),
+    // Durations and tick rates are in seconds
+    DoT {
+        damage: i32,
+        duration: f32,
+        tick_rate: f32,
+    },
+    // `factor` multiplies the enemy's movement speed
+    Slow {
+        factor: f32,
+        duration: f32,
+    },
+    Stun {
+        duration: f32,
+    },
+    // Damages every enemy within `radius` of the impact
+    Area {
+        radius: f32,
+        damage: i32,
+    },
+}
+
+impl ProjectileEffect {
+    // Make an effect that deals damage hit harder. Other effects are left as
+    // they are.
+    pub fn add_damage(&mut self, bonus: i32) {
+        match self {
+            ProjectileEffect::Damage(damage)
+            | ProjectileEffect::DoT { damage, .. }
+            | ProjectileEffect::Area { damage, .. } => *damage += bonus,
+            ProjectileEffect::Slow { .. } | ProjectileEffect::Stun { .. } => {}
+        }
+    }
 }
 
 pub struct ProjectileSystem;
 
 impl<'s> System<'s> for ProjectileSystem {
     type SystemData = (
-        ReadStorage<'s, Projectile>,
+        WriteStorage<'s, Projectile>,
+        ReadStorage<'s, Flight>,
+        ReadStorage<'s, Homing>,
         ReadStorage<'s, Transform>,
         WriteStorage<'s, Enemy>,
+        WriteStorage<'s, StatusEffects>,
+        Read<'s, SpatialGrid>,
         Entities<'s>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (projectiles, transforms, mut enemies, entities) = data;
-        for (projectile_entity, projectile, projectile_transform) in
-            (&entities, &projectiles, &transforms).join()
+        let (
+            mut projectiles,
+            flights,
+            homings,
+            transforms,
+            mut enemies,
+            mut status_effects,
+            spatial_grid,
+            entities,
+        ) = data;
+        for (projectile_entity, projectile, flight, homing, projectile_transform) in (
+            &entities,
+            &mut projectiles,
+            flights.maybe(),
+            homings.maybe(),
+            &transforms,
+        )
+            .join()
         {
-            for (enemy, enemy_transform) in (&mut enemies, &transforms).join() {
-                let distance_vector =
-                    projectile_transform.translation() - enemy_transform.translation();
-                let distance_sq = (distance_vector.x * distance_vector.x)
-                    + (distance_vector.y * distance_vector.y);
-                if distance_sq < 100.0 {
-                    match projectile.effect {
-                        ProjectileEffect::Damage(damage) => enemy.health -= damage,
-                        _ => {}
+            // Projectiles in flight pass over enemies until they land
+            let has_landed = match flight {
+                Some(flight) if !flight.has_landed() => continue,
+                Some(_) => true,
+                None => false,
+            };
+            let impact = projectile_transform.translation();
+            let projectile_radius = projectile.size * 0.5;
+            let mut touching = spatial_grid
+                .within_radius(impact, projectile_radius + spatial_grid.largest_radius())
+                .into_iter()
+                .filter_map(|enemy_entity| {
+                    let enemy = enemies.get(enemy_entity)?;
+                    let enemy_transform = transforms.get(enemy_entity)?;
+                    let reach = projectile_radius + enemy.size * 0.5;
+                    let enemy_distance_sq = distance_sq(impact, enemy_transform.translation());
+                    let can_hit = !projectile.hit_enemies.contains(&enemy_entity)
+                        && homing.map_or(true, |homing| homing.can_hit(enemy_entity));
+                    if can_hit && enemy_distance_sq < reach * reach {
+                        Some((enemy_entity, enemy_distance_sq))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            // The closest enemies are hit first
+            touching.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+            let hit_enemies = touching
+                .into_iter()
+                .take(projectile.hits_remaining as usize)
+                .map(|(enemy_entity, _)| enemy_entity)
+                .collect::<Vec<_>>();
+            // A landed projectile goes off even if it missed, so its area
+            // effects still apply
+            if hit_enemies.is_empty() && !has_landed {
+                continue;
+            }
+            for effect in projectile.effects.iter() {
+                match *effect {
+                    // Area effects go off once per impact rather than once per enemy hit
+                    ProjectileEffect::Area { radius, damage } => {
+                        for enemy_entity in spatial_grid.within_radius(impact, radius) {
+                            if let Some(enemy) = enemies.get_mut(enemy_entity) {
+                                enemy.health -= damage;
+                            }
+                        }
+                    }
+                    _ => {
+                        for enemy_entity in hit_enemies.iter() {
+                            apply_effect(effect, *enemy_entity, &mut enemies, &mut status_effects);
+                        }
                     }
-                    let _ = entities.delete(projectile_entity);
                 }
             }
+            projectile.hits_remaining -= hit_enemies.len() as u32;
+            projectile.hit_enemies.extend(hit_enemies);
+            if projectile.hits_remaining == 0 || has_landed {
+                let _ = entities.delete(projectile_entity);
+            }
+        }
+    }
+}
+
+// Apply an effect that only affects the enemy that was hit
+fn apply_effect(
+    effect: &ProjectileEffect,
+    enemy_entity: Entity,
+    enemies: &mut WriteStorage<Enemy>,
+    status_effects: &mut WriteStorage<StatusEffects>,
+) {
+    let status_effect = match *effect {
+        ProjectileEffect::Damage(damage) => {
+            if let Some(enemy) = enemies.get_mut(enemy_entity) {
+                enemy.health -= damage;
+            }
+            return;
         }
+        ProjectileEffect::DoT {
+            damage,
+            duration,
+            tick_rate,
+        } => StatusEffect::damage_over_time(damage, duration, tick_rate),
+        ProjectileEffect::Slow { factor, duration } => StatusEffect::slow(factor, duration),
+        ProjectileEffect::Stun { duration } => StatusEffect::stun(duration),
+        ProjectileEffect::Area { .. } => return,
+    };
+    if let Some(enemy_status_effects) = status_effects.get_mut(enemy_entity) {
+        enemy_status_effects.apply(status_effect);
     }
 }
 
@@ -73,25 +427,71 @@ pub fn create_projectile(
     sprite_sheet: Handle<SpriteSheet>,
     origin: Vector3<f32>,
     target: Vector3<f32>,
-    speed: f32,
+    target_entity: Entity,
+    definition: &TowerDefinition,
 ) {
     let projectile_sprite = SpriteRender {
         sprite_sheet: sprite_sheet.clone(),
-        sprite_number: 1,
+        sprite_number: definition.projectile_sprite_number,
     };
 
     let mut transform = Transform::default();
     transform.set_translation(origin);
 
-    let velocity = Velocity::new((target - origin).normalize() * speed);
-
+    let max_range = match definition.trajectory {
+        Trajectory::Straight => Some(definition.range + RANGE_MARGIN),
+        Trajectory::Eased { .. } | Trajectory::Homing { .. } => None,
+    };
     let projectile = Projectile {
-        effect: ProjectileEffect::Damage(10),
+        effects: definition.projectile_effects.clone(),
+        origin,
+        max_range,
+        lifetime: PROJECTILE_LIFETIME,
+        size: definition.projectile_size,
+        hits_remaining: definition.pierce + 1,
+        hit_enemies: Vec::new(),
     };
 
     let entity = entities.create();
     lazy_update.insert(entity, projectile_sprite);
     lazy_update.insert(entity, transform);
-    lazy_update.insert(entity, velocity);
     lazy_update.insert(entity, projectile);
+    match definition.trajectory {
+        Trajectory::Straight => {
+            let velocity =
+                Velocity::new((target - origin).normalize() * definition.projectile_speed);
+            lazy_update.insert(entity, velocity);
+        }
+        Trajectory::Eased {
+            easing,
+            flight_time,
+            arc_height,
+        } => {
+            let flight = Flight {
+                origin,
+                target,
+                easing,
+                arc_height,
+                duration: flight_time,
+                elapsed: 0.0,
+            };
+            lazy_update.insert(entity, flight);
+        }
+        Trajectory::Homing {
+            turn_rate,
+            on_target_lost,
+            hits_others,
+        } => {
+            let velocity =
+                Velocity::new((target - origin).normalize() * definition.projectile_speed);
+            let homing = Homing {
+                target: target_entity,
+                turn_rate,
+                on_target_lost,
+                hits_others,
+            };
+            lazy_update.insert(entity, velocity);
+            lazy_update.insert(entity, homing);
+        }
+    }
 }
@@ -0,0 +1,24 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+// Every random decision that affects gameplay draws from this generator, so
+// a run can be reproduced by starting the game with the same seed.
+pub struct GameSeed {
+    pub seed: u64,
+    pub rng: StdRng,
+}
+
+impl GameSeed {
+    pub fn new(seed: u64) -> Self {
+        GameSeed {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    // A generator for a single wave. It doesn't depend on how much of `rng`
+    // has been used, so a generated wave only depends on the seed and its
+    // number.
+    pub fn wave_rng(&self, wave_number: usize) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ (wave_number as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+
+use amethyst::{
+    ecs::prelude::{
+        Entities, Entity, Read, ReadExpect, System, SystemData, World, Write, WriteStorage,
+    },
+    input::{Button, InputHandler, StringBindings},
+    renderer::{palette::Srgba, resources::Tint, SpriteRender},
+    shrev::{EventChannel, ReaderId},
+    ui::{UiEvent, UiEventType},
+};
+
+use crate::{
+    placement::TileOccupancy,
+    tower::{Tower, TowerCatalog},
+    wave::WaveState,
+    BuildingMaterials, GameUi,
+};
+
+pub const SELL_ACTION: &str = "sell_tower";
+// Buy the first and second of the selected tower's next upgrades
+pub const UPGRADE_ACTIONS: [&str; 2] = ["upgrade_tower_1", "upgrade_tower_2"];
+
+// The tower the player last clicked on
+#[derive(Default)]
+pub struct TowerSelection {
+    pub tower: Option<Entity>,
+}
+
+// Remembers which hotkeys were held last frame, so holding one down only
+// triggers it once
+#[derive(Default)]
+struct Hotkeys {
+    held: HashSet<&'static str>,
+}
+
+impl Hotkeys {
+    fn pressed(&mut self, input: &InputHandler<StringBindings>, action: &'static str) -> bool {
+        let is_down = input.action_is_down(action).unwrap_or(false);
+        let was_down = if is_down {
+            !self.held.insert(action)
+        } else {
+            self.held.remove(action)
+        };
+        is_down && !was_down
+    }
+}
+
+// How the first key combination bound to `action` is shown to the player,
+// like "U" or "LControl+S"
+pub fn hotkey_label(input: &InputHandler<StringBindings>, action: &str) -> Option<String> {
+    let buttons = input.bindings.action_bindings(action).next()?;
+    let labels = buttons
+        .iter()
+        .map(|button| match button {
+            Button::Key(key) => format!("{:?}", key).trim_start_matches("Key").to_string(),
+            button => format!("{:?}", button),
+        })
+        .collect::<Vec<_>>();
+    Some(labels.join("+"))
+}
+
+// Applies hotkeys and button clicks to the selected tower
+#[derive(Default)]
+pub struct TowerSelectionSystem {
+    hotkeys: Hotkeys,
+    ui_reader_id: Option<ReaderId<UiEvent>>,
+}
+
+impl<'s> System<'s> for TowerSelectionSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Tower>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Tint>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, EventChannel<UiEvent>>,
+        Write<'s, TowerSelection>,
+        Write<'s, BuildingMaterials>,
+        Write<'s, TileOccupancy>,
+        ReadExpect<'s, TowerCatalog>,
+        ReadExpect<'s, WaveState>,
+        ReadExpect<'s, GameUi>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            mut towers,
+            mut sprite_renders,
+            mut tints,
+            input,
+            ui_events,
+            mut tower_selection,
+            mut building_materials,
+            mut tile_occupancy,
+            tower_catalog,
+            wave_state,
+            game_ui,
+        ) = data;
+
+        let cycle_targeting = self.hotkeys.pressed(&input, "cycle_targeting");
+        let mut sell = self.hotkeys.pressed(&input, SELL_ACTION);
+        // The first upgrade hotkey also buys the only upgrade of towers that
+        // aren't choosing between branches
+        let upgrade_1 = self.hotkeys.pressed(&input, UPGRADE_ACTIONS[0]);
+        let upgrade_2 = self.hotkeys.pressed(&input, UPGRADE_ACTIONS[1]);
+        let mut upgrade = if upgrade_1 {
+            Some(0)
+        } else if upgrade_2 {
+            Some(1)
+        } else {
+            None
+        };
+        for event in ui_events.read(self.ui_reader_id.as_mut().unwrap()) {
+            if event.event_type == UiEventType::Click {
+                if event.target == game_ui.sell_button {
+                    sell = true;
+                }
+                if let Some(index) = game_ui
+                    .upgrade_buttons
+                    .iter()
+                    .position(|button| *button == event.target)
+                {
+                    upgrade = Some(index);
+                }
+            }
+        }
+
+        let selected = match tower_selection.tower {
+            Some(selected) if entities.is_alive(selected) => selected,
+            _ => {
+                tower_selection.tower = None;
+                return;
+            }
+        };
+        let tower = match towers.get_mut(selected) {
+            Some(tower) => tower,
+            None => return,
+        };
+        if cycle_targeting {
+            tower.targeting = tower.targeting.next();
+        }
+        if sell {
+            building_materials.coins += tower.sell_value(tower_catalog.sell_refund, &wave_state);
+            tile_occupancy.remove_tower(selected);
+            tower_selection.tower = None;
+            let _ = entities.delete(selected);
+            return;
+        }
+
+        let option = match upgrade {
+            Some(option) => option,
+            None => return,
+        };
+        let definition = match tower_catalog.get(&tower.kind) {
+            Some(definition) => definition,
+            None => return,
+        };
+        let (branch, next_upgrade) = match definition
+            .upgrades
+            .next_upgrades(tower.level, tower.branch)
+            .get(option)
+        {
+            Some(&next) => next,
+            None => return,
+        };
+        if building_materials.coins < next_upgrade.cost {
+            return;
+        }
+
+        building_materials.coins -= next_upgrade.cost;
+        tower.invested += next_upgrade.cost;
+        tower.level += 1;
+        tower.branch = branch.or(tower.branch);
+        tower.stats = definition.upgraded(
+            definition
+                .upgrades
+                .bought(tower.level, tower.branch)
+                .into_iter(),
+        );
+        if let Some(sprite_render) = sprite_renders.get_mut(selected) {
+            sprite_render.sprite_number = tower.stats.sprite_number;
+        }
+        if let Some([r, g, b, a]) = tower.stats.tint {
+            let _ = tints.insert(selected, Tint(Srgba::new(r, g, b, a)));
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.ui_reader_id = Some(world.fetch_mut::<EventChannel<UiEvent>>().register_reader());
+    }
+}
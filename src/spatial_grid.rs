@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+
+use amethyst::{
+    core::{math::Vector3, transform::Transform},
+    ecs::prelude::{Entities, Entity, Join, ReadStorage, System, Write},
+};
+
+use crate::{
+    enemy::Enemy,
+    tile_map::{world_to_tile, TILE_SIZE},
+};
+
+/** Enemies bucketed by the tile they are on, so range queries only look at nearby tiles */
+#[derive(Default)]
+pub struct SpatialGrid {
+    cells: HashMap<(i32, i32), Vec<(Entity, Vector3<f32>)>>,
+    // Half the size of the biggest enemy in the grid, so collision queries
+    // can widen their radius enough to catch it
+    largest_radius: f32,
+}
+
+impl SpatialGrid {
+    pub fn clear(&mut self) {
+        // Keep the cell vectors around so rebuilding doesn't reallocate
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+        self.largest_radius = 0.0;
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vector3<f32>, size: f32) {
+        self.largest_radius = self.largest_radius.max(size * 0.5);
+        self.cells
+            .entry(world_to_tile(&position))
+            .or_insert_with(Vec::new)
+            .push((entity, position));
+    }
+
+    // Every enemy within `radius` of `position`. Like the rest of the game
+    // this ignores depth.
+    pub fn within_radius(&self, position: &Vector3<f32>, radius: f32) -> Vec<Entity> {
+        let (min_x, min_y) = world_to_tile(&(position - Vector3::new(radius, radius, 0.0)));
+        let (max_x, max_y) = world_to_tile(&(position + Vector3::new(radius, radius, 0.0)));
+        let mut found = Vec::new();
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                for (entity, entity_position) in self.cell((x, y)) {
+                    if distance_sq(position, entity_position) <= radius * radius {
+                        found.push(*entity);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    // The enemy closest to `position` that is no further than `max_distance`
+    // away and passes `filter`
+    pub fn nearest<F>(
+        &self,
+        position: &Vector3<f32>,
+        max_distance: f32,
+        filter: F,
+    ) -> Option<Entity>
+    where
+        F: Fn(Entity) -> bool,
+    {
+        let center = world_to_tile(position);
+        let max_ring = (max_distance / TILE_SIZE).ceil() as i32 + 1;
+        let mut nearest: Option<(Entity, f32)> = None;
+        // Search rings of tiles around the center until nothing closer than
+        // the best match so far can be found
+        for ring in 0..=max_ring {
+            for x in (center.0 - ring)..=(center.0 + ring) {
+                for y in (center.1 - ring)..=(center.1 + ring) {
+                    let on_ring = (x - center.0).abs() == ring || (y - center.1).abs() == ring;
+                    if !on_ring {
+                        continue;
+                    }
+                    for (entity, entity_position) in self.cell((x, y)) {
+                        let entity_distance_sq = distance_sq(position, entity_position);
+                        let is_closer = nearest.map_or(true, |(_, best)| entity_distance_sq < best);
+                        if entity_distance_sq <= max_distance * max_distance
+                            && is_closer
+                            && filter(*entity)
+                        {
+                            nearest = Some((*entity, entity_distance_sq));
+                        }
+                    }
+                }
+            }
+            // Everything on the next ring is at least this far away
+            let next_ring_distance = ring as f32 * TILE_SIZE;
+            if let Some((_, best)) = nearest {
+                if best <= next_ring_distance * next_ring_distance {
+                    break;
+                }
+            }
+        }
+        nearest.map(|(entity, _)| entity)
+    }
+
+    pub fn largest_radius(&self) -> f32 {
+        self.largest_radius
+    }
+
+    fn cell(&self, coord: (i32, i32)) -> &[(Entity, Vector3<f32>)] {
+        self.cells.get(&coord).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+// Rebuilds the grid from scratch each step. Enemies move every step, so
+// updating it in place would touch nearly every entry anyway.
+pub struct SpatialGridSystem;
+
+impl<'s> System<'s> for SpatialGridSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Enemy>,
+        ReadStorage<'s, Transform>,
+        Write<'s, SpatialGrid>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, enemies, transforms, mut spatial_grid) = data;
+        spatial_grid.clear();
+        for (entity, enemy, transform) in (&entities, &enemies, &transforms).join() {
+            spatial_grid.insert(entity, *transform.translation(), enemy.size);
+        }
+    }
+}
+
+// Squared distance between two positions, ignoring depth. Every range check
+// in the game goes through this so they all agree on what is in range.
+pub fn distance_sq(a: &Vector3<f32>, b: &Vector3<f32>) -> f32 {
+    let distance_vector = a - b;
+    (distance_vector.x * distance_vector.x) + (distance_vector.y * distance_vector.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use amethyst::ecs::prelude::{Builder, World, WorldExt};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    // Enemies at `positions` in both a grid and a plain list to check it against
+    fn grid_of(
+        world: &mut World,
+        positions: &[(f32, f32)],
+    ) -> (SpatialGrid, Vec<(Entity, Vector3<f32>)>) {
+        let mut spatial_grid = SpatialGrid::default();
+        let mut enemies = Vec::new();
+        for &(x, y) in positions {
+            let entity = world.create_entity().build();
+            let position = Vector3::new(x, y, 0.0);
+            spatial_grid.insert(entity, position, 8.0);
+            enemies.push((entity, position));
+        }
+        (spatial_grid, enemies)
+    }
+
+    fn random_positions(rng: &mut StdRng, count: usize) -> Vec<(f32, f32)> {
+        (0..count)
+            .map(|_| (rng.gen_range(-64.0, 704.0), rng.gen_range(-64.0, 544.0)))
+            .collect()
+    }
+
+    fn scan_within_radius(
+        enemies: &[(Entity, Vector3<f32>)],
+        position: &Vector3<f32>,
+        radius: f32,
+    ) -> Vec<Entity> {
+        enemies
+            .iter()
+            .filter(|(_, enemy_position)| distance_sq(position, enemy_position) <= radius * radius)
+            .map(|(entity, _)| *entity)
+            .collect()
+    }
+
+    fn scan_nearest<F>(
+        enemies: &[(Entity, Vector3<f32>)],
+        position: &Vector3<f32>,
+        max_distance: f32,
+        filter: F,
+    ) -> Option<f32>
+    where
+        F: Fn(Entity) -> bool,
+    {
+        enemies
+            .iter()
+            .filter(|(entity, _)| filter(*entity))
+            .map(|(_, enemy_position)| distance_sq(position, enemy_position))
+            .filter(|distance| *distance <= max_distance * max_distance)
+            .fold(None, |nearest: Option<f32>, distance| {
+                Some(nearest.map_or(distance, |nearest| nearest.min(distance)))
+            })
+    }
+
+    fn sorted(mut entities: Vec<Entity>) -> Vec<Entity> {
+        entities.sort_by_key(|entity| entity.id());
+        entities
+    }
+
+    fn assert_matches_scan(
+        spatial_grid: &SpatialGrid,
+        enemies: &[(Entity, Vector3<f32>)],
+        position: &Vector3<f32>,
+        radius: f32,
+    ) {
+        assert_eq!(
+            sorted(spatial_grid.within_radius(position, radius)),
+            sorted(scan_within_radius(enemies, position, radius)),
+            "within {} of {:?}",
+            radius,
+            position
+        );
+        // Ties can pick either enemy, so compare distances
+        let nearest = spatial_grid
+            .nearest(position, radius, |_| true)
+            .map(|entity| {
+                let (_, enemy_position) =
+                    enemies.iter().find(|(other, _)| *other == entity).unwrap();
+                distance_sq(position, enemy_position)
+            });
+        assert_eq!(
+            nearest,
+            scan_nearest(enemies, position, radius, |_| true),
+            "nearest within {} of {:?}",
+            radius,
+            position
+        );
+    }
+
+    #[test]
+    fn queries_match_a_linear_scan() {
+        let mut world = World::new();
+        let mut rng = StdRng::seed_from_u64(17);
+        let positions = random_positions(&mut rng, 500);
+        let (spatial_grid, enemies) = grid_of(&mut world, &positions);
+        for _ in 0..500 {
+            let position = Vector3::new(
+                rng.gen_range(-80.0, 720.0),
+                rng.gen_range(-80.0, 560.0),
+                0.0,
+            );
+            let radius = rng.gen_range(0.0, 100.0);
+            assert_matches_scan(&spatial_grid, &enemies, &position, radius);
+        }
+    }
+
+    #[test]
+    fn queries_match_a_linear_scan_on_tile_edges() {
+        let mut world = World::new();
+        // Enemies on tile corners and edges, including negative tiles
+        let positions = (-3..=3)
+            .flat_map(|x| {
+                (-3..=3).map(move |y| (x as f32 * TILE_SIZE * 0.5, y as f32 * TILE_SIZE * 0.5))
+            })
+            .collect::<Vec<_>>();
+        let (spatial_grid, enemies) = grid_of(&mut world, &positions);
+        for &(x, y) in [
+            (0.0, 0.0),
+            (TILE_SIZE, 0.0),
+            (-TILE_SIZE, TILE_SIZE * 0.5),
+            (4.0, -12.0),
+        ]
+        .iter()
+        {
+            let position = Vector3::new(x, y, 0.0);
+            for &radius in [
+                0.0,
+                TILE_SIZE * 0.5,
+                TILE_SIZE,
+                TILE_SIZE * 1.5,
+                TILE_SIZE * 3.0,
+            ]
+            .iter()
+            {
+                assert_matches_scan(&spatial_grid, &enemies, &position, radius);
+            }
+        }
+    }
+
+    #[test]
+    fn radius_boundary_is_inclusive() {
+        let mut world = World::new();
+        let (spatial_grid, enemies) = grid_of(&mut world, &[(40.0, 8.0), (8.0, 40.5)]);
+        let position = Vector3::new(8.0, 8.0, 0.0);
+        // The first enemy is exactly 32 away, the second just further
+        assert_eq!(
+            spatial_grid.within_radius(&position, 32.0),
+            vec![enemies[0].0]
+        );
+        assert_eq!(
+            spatial_grid.nearest(&position, 32.0, |_| true),
+            Some(enemies[0].0)
+        );
+        assert_eq!(spatial_grid.within_radius(&position, 31.9), vec![]);
+        assert_eq!(spatial_grid.nearest(&position, 31.9, |_| true), None);
+    }
+
+    #[test]
+    fn nearest_looks_past_its_own_tile() {
+        let mut world = World::new();
+        // The enemy sharing the query's tile is further than the one across
+        // the tile edge
+        let (spatial_grid, enemies) = grid_of(&mut world, &[(1.0, 1.0), (17.0, 15.0)]);
+        let position = Vector3::new(15.0, 15.0, 0.0);
+        assert_eq!(
+            spatial_grid.nearest(&position, 64.0, |_| true),
+            Some(enemies[1].0)
+        );
+        // Filtered out enemies are skipped, not treated as blocking
+        let filtered = spatial_grid.nearest(&position, 64.0, |entity| entity != enemies[1].0);
+        assert_eq!(filtered, Some(enemies[0].0));
+    }
+
+    // Times both queries against a linear scan over a few thousand enemies.
+    // Run with `cargo test --release -- --ignored --nocapture bench`.
+    #[test]
+    #[ignore]
+    fn bench_queries_against_linear_scan() {
+        const ENEMIES: usize = 4000;
+        const QUERIES: usize = 10_000;
+        let mut world = World::new();
+        let mut rng = StdRng::seed_from_u64(42);
+        let positions = random_positions(&mut rng, ENEMIES);
+        let (spatial_grid, enemies) = grid_of(&mut world, &positions);
+        let queries = (0..QUERIES)
+            .map(|_| Vector3::new(rng.gen_range(0.0, 640.0), rng.gen_range(0.0, 480.0), 0.0))
+            .collect::<Vec<_>>();
+        let radius = 48.0;
+
+        let time = |name: &str, query: &dyn Fn(&Vector3<f32>) -> usize| {
+            let start = Instant::now();
+            let found = queries.iter().map(query).sum::<usize>();
+            println!(
+                "{:>22}: {:>8.2?} for {} queries ({} found)",
+                name,
+                start.elapsed(),
+                QUERIES,
+                found
+            );
+        };
+        time("grid within_radius", &|position| {
+            spatial_grid.within_radius(position, radius).len()
+        });
+        time("scan within_radius", &|position| {
+            scan_within_radius(&enemies, position, radius).len()
+        });
+        time("grid nearest", &|position| {
+            spatial_grid.nearest(position, radius, |_| true).is_some() as usize
+        });
+        time("scan nearest", &|position| {
+            scan_nearest(&enemies, position, radius, |_| true).is_some() as usize
+        });
+    }
+}
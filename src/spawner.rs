@@ -1,66 +1,43 @@
 use amethyst::{
-    core::{math::Vector3, timing::Time, transform::Transform},
-    ecs::prelude::{
-        Component, DenseVecStorage, Entities, Join, LazyUpdate, Read, ReadExpect, ReadStorage,
-        System, WriteStorage,
-    },
+    core::{math::Vector3, transform::Transform},
+    ecs::prelude::{Component, DenseVecStorage},
     prelude::*,
 };
-use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 
-use crate::{
-    enemy::{create_enemy, EnemyType},
-    sprite::SpriteSheetMap,
-};
+use crate::tile_map::{tile_center, world_to_tile, EnemyPath};
+
+/** The id waves use to pick which spawn point a group of enemies comes from */
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct SpawnerId(pub u32);
 
+// A spawn point at the start of an enemy path. Waves decide what spawns here
+// and when.
 #[derive(Component)]
 pub struct Spawner {
-    speed: f32,
-    last_spawn_time: f64,
+    pub id: SpawnerId,
+    // Tile-center waypoints that spawned enemies walk along
+    pub waypoints: Vec<Vector3<f32>>,
 }
 
-pub struct SpawnerSystem;
-
-impl<'s> System<'s> for SpawnerSystem {
-    type SystemData = (
-        WriteStorage<'s, Spawner>,
-        ReadStorage<'s, Transform>,
-        ReadExpect<'s, LazyUpdate>,
-        Read<'s, SpriteSheetMap>,
-        Read<'s, Time>,
-        Entities<'s>,
-    );
-
-    fn run(&mut self, data: Self::SystemData) {
-        let (mut spawners, transforms, lazy_update, sprite_sheet_map, time, entities) = data;
-        for (transform, spawner) in (&transforms, &mut spawners).join() {
-            let current_time = time.absolute_time_seconds();
-            if spawner.last_spawn_time + (spawner.speed as f64) < current_time {
-                let mut rng = rand::thread_rng();
-                let enemy_type = [EnemyType::JumpingJelly, EnemyType::SlideySlime]
-                    .choose(&mut rng)
-                    .unwrap();
-                spawner.last_spawn_time = current_time;
-                create_enemy(
-                    &entities,
-                    &lazy_update,
-                    sprite_sheet_map.clone(),
-                    *transform.translation(),
-                    *enemy_type,
-                );
-            }
-        }
+impl Spawner {
+    // Whether enemies from here walk over the tile
+    pub fn is_on_path(&self, coord: (i32, i32)) -> bool {
+        self.waypoints
+            .iter()
+            .any(|waypoint| world_to_tile(waypoint) == coord)
     }
 }
 
-pub fn create_spawner(world: &mut World, origin: Vector3<f32>) {
+pub fn create_spawner(world: &mut World, id: SpawnerId, enemy_path: &EnemyPath) {
     let spawner = Spawner {
-        speed: 2.0,
-        last_spawn_time: 0.0,
+        id,
+        waypoints: enemy_path.waypoints(),
     };
 
     let mut transform = Transform::default();
-    transform.set_translation(origin);
+    transform.set_translation(tile_center(enemy_path.starting_coord));
 
     world.create_entity().with(spawner).with(transform).build();
 }
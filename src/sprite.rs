@@ -2,8 +2,13 @@ use std::collections::HashMap;
 
 use amethyst::{
     assets::{AssetStorage, Loader},
+    core::timing::Time,
+    ecs::prelude::{Component, DenseVecStorage, Join, Read, System, WriteStorage},
     prelude::*,
-    renderer::{sprite::SpriteSheetHandle, ImageFormat, SpriteSheet, SpriteSheetFormat, Texture},
+    renderer::{
+        sprite::SpriteSheetHandle, ImageFormat, SpriteRender, SpriteSheet, SpriteSheetFormat,
+        Texture,
+    },
 };
 use serde::{Deserialize, Serialize};
 
@@ -64,3 +69,51 @@ pub fn load_sprite_sheet(world: &mut World, asset_name: &str) -> SpriteSheetHand
         &spritesheet_store,
     )
 }
+
+/** Loops through a run of consecutive sprites in a sprite sheet */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpriteAnimationDefinition {
+    pub first_sprite: usize,
+    pub frame_count: usize,
+    // Seconds each frame is shown for
+    pub frame_duration: f32,
+}
+
+#[derive(Component)]
+pub struct SpriteAnimation {
+    definition: SpriteAnimationDefinition,
+    elapsed: f32,
+}
+
+impl SpriteAnimation {
+    pub fn new(definition: SpriteAnimationDefinition) -> Self {
+        SpriteAnimation {
+            definition,
+            elapsed: 0.0,
+        }
+    }
+}
+
+pub struct SpriteAnimationSystem;
+
+impl<'s> System<'s> for SpriteAnimationSystem {
+    type SystemData = (
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, SpriteAnimation>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut sprite_renders, mut animations, time) = data;
+        for (sprite_render, animation) in (&mut sprite_renders, &mut animations).join() {
+            let definition = &animation.definition;
+            if definition.frame_count == 0 || definition.frame_duration <= 0.0 {
+                continue;
+            }
+            let loop_duration = definition.frame_duration * definition.frame_count as f32;
+            animation.elapsed = (animation.elapsed + time.delta_seconds()) % loop_duration;
+            let frame = (animation.elapsed / definition.frame_duration) as usize;
+            sprite_render.sprite_number = definition.first_sprite + frame % definition.frame_count;
+        }
+    }
+}
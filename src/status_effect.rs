@@ -0,0 +1,181 @@
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::{Component, DenseVecStorage, Join, Read, System, WriteStorage},
+};
+
+use crate::enemy::Enemy;
+
+// Damage over time from separate hits stacks up to this many times
+const MAX_DOT_STACKS: usize = 3;
+
+#[derive(Clone, Debug)]
+pub enum StatusEffectKind {
+    // Deals `damage` every `tick_rate` seconds
+    DoT {
+        damage: i32,
+        tick_rate: f32,
+        next_tick: f32,
+    },
+    // Multiplies movement speed by `factor`
+    Slow {
+        factor: f32,
+    },
+    Stun,
+}
+
+#[derive(Clone, Debug)]
+pub struct StatusEffect {
+    kind: StatusEffectKind,
+    // Both in seconds
+    duration: f32,
+    elapsed: f32,
+}
+
+impl StatusEffect {
+    pub fn damage_over_time(damage: i32, duration: f32, tick_rate: f32) -> Self {
+        StatusEffect::new(
+            StatusEffectKind::DoT {
+                damage,
+                tick_rate,
+                next_tick: tick_rate,
+            },
+            duration,
+        )
+    }
+
+    pub fn slow(factor: f32, duration: f32) -> Self {
+        StatusEffect::new(StatusEffectKind::Slow { factor }, duration)
+    }
+
+    pub fn stun(duration: f32) -> Self {
+        StatusEffect::new(StatusEffectKind::Stun, duration)
+    }
+
+    fn new(kind: StatusEffectKind, duration: f32) -> Self {
+        StatusEffect {
+            kind,
+            duration,
+            elapsed: 0.0,
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.duration - self.elapsed
+    }
+
+    fn is_expired(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    fn is_dot(&self) -> bool {
+        matches!(self.kind, StatusEffectKind::DoT { .. })
+    }
+}
+
+/** All status effects currently active on an enemy */
+#[derive(Component, Default)]
+pub struct StatusEffects {
+    effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    // Stacking rules:
+    // - Damage over time stacks up to `MAX_DOT_STACKS`; past that the stack
+    //   closest to running out is replaced.
+    // - Slows of the same strength don't stack, reapplying one refreshes its
+    //   duration. Slows of different strengths are kept side by side and only
+    //   the strongest one affects movement.
+    // - Stuns don't stack, reapplying one refreshes its duration.
+    pub fn apply(&mut self, effect: StatusEffect) {
+        let existing = match effect.kind {
+            StatusEffectKind::DoT { .. } => {
+                let stacks = self.effects.iter().filter(|active| active.is_dot()).count();
+                if stacks < MAX_DOT_STACKS {
+                    self.effects.push(effect);
+                    return;
+                }
+                let closest_to_expiring = self
+                    .effects
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, active)| active.is_dot())
+                    .min_by(|(_, a), (_, b)| a.remaining().partial_cmp(&b.remaining()).unwrap())
+                    .map(|(index, _)| index);
+                if let Some(index) = closest_to_expiring {
+                    self.effects[index] = effect;
+                }
+                return;
+            }
+            StatusEffectKind::Slow { factor } => {
+                self.effects.iter().position(|active| match active.kind {
+                    StatusEffectKind::Slow {
+                        factor: active_factor,
+                    } => (active_factor - factor).abs() < std::f32::EPSILON,
+                    _ => false,
+                })
+            }
+            StatusEffectKind::Stun => self
+                .effects
+                .iter()
+                .position(|active| matches!(active.kind, StatusEffectKind::Stun)),
+        };
+
+        match existing {
+            Some(index) => {
+                let active = &mut self.effects[index];
+                if effect.duration > active.remaining() {
+                    active.duration = effect.duration;
+                    active.elapsed = 0.0;
+                }
+            }
+            None => self.effects.push(effect),
+        }
+    }
+
+    // The fraction of its normal speed the enemy currently moves at
+    pub fn speed_multiplier(&self) -> f32 {
+        let mut multiplier: f32 = 1.0;
+        for effect in self.effects.iter() {
+            match effect.kind {
+                StatusEffectKind::Stun => return 0.0,
+                StatusEffectKind::Slow { factor } => multiplier = multiplier.min(factor),
+                _ => {}
+            }
+        }
+        multiplier.max(0.0)
+    }
+}
+
+pub struct StatusEffectSystem;
+
+impl<'s> System<'s> for StatusEffectSystem {
+    type SystemData = (
+        WriteStorage<'s, StatusEffects>,
+        WriteStorage<'s, Enemy>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut status_effects, mut enemies, time) = data;
+        let delta_seconds = time.fixed_seconds();
+        for (status_effects, enemy) in (&mut status_effects, &mut enemies).join() {
+            for effect in status_effects.effects.iter_mut() {
+                effect.elapsed += delta_seconds;
+                let active_until = effect.elapsed.min(effect.duration);
+                if let StatusEffectKind::DoT {
+                    damage,
+                    tick_rate,
+                    ref mut next_tick,
+                } = effect.kind
+                {
+                    // Catch up on every tick that happened since the last step
+                    while tick_rate > 0.0 && *next_tick <= active_until {
+                        enemy.health -= damage;
+                        *next_tick += tick_rate;
+                    }
+                }
+            }
+            status_effects.effects.retain(|effect| !effect.is_expired());
+        }
+    }
+}
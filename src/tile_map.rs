@@ -1,25 +1,103 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, mem,
+};
+
+use amethyst::{core::math::Vector3, error::Error};
 use rand::{
     distributions::{Distribution, Standard},
-    seq::SliceRandom,
     Rng,
 };
+use serde::{Deserialize, Serialize};
 
-const ALL_DIRECTIONS: [Direction; 4] = [
+pub const TILE_SIZE: f32 = 16.0;
+
+pub const ALL_DIRECTIONS: [Direction; 4] = [
     Direction::Up,
     Direction::Down,
     Direction::Right,
     Direction::Left,
 ];
 
+#[derive(Deserialize, Serialize)]
 pub struct EnemyPath {
     pub starting_coord: (i32, i32),
     pub path: Vec<Direction>,
 }
 
+impl EnemyPath {
+    // Build a path from consecutive, neighboring tile coordinates
+    pub fn from_coords(coords: &[(i32, i32)]) -> Result<Self, Error> {
+        let starting_coord = *coords
+            .first()
+            .ok_or_else(|| Error::from_string("Path has no tiles"))?;
+        let path = coords
+            .windows(2)
+            .map(|step| {
+                let movement_coord = (step[1].0 - step[0].0, step[1].1 - step[0].1);
+                ALL_DIRECTIONS
+                    .iter()
+                    .find(|&&direction| direction_to_coord(direction) == movement_coord)
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::from_string(format!(
+                            "Path steps from {:?} to {:?}, which aren't neighbors",
+                            step[0], step[1]
+                        ))
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(EnemyPath {
+            starting_coord,
+            path,
+        })
+    }
+
+    // Return every tile coordinate along the path, including the starting one.
+    pub fn coords(&self) -> Vec<(i32, i32)> {
+        let mut coords = vec![self.starting_coord];
+        let mut cursor_coord = self.starting_coord;
+        for direction in self.path.iter() {
+            let movement_coord = direction_to_coord(*direction);
+            cursor_coord = (
+                cursor_coord.0 + movement_coord.0,
+                cursor_coord.1 + movement_coord.1,
+            );
+            coords.push(cursor_coord);
+        }
+        coords
+    }
+
+    // Return the world position of the center of every tile along the path.
+    pub fn waypoints(&self) -> Vec<Vector3<f32>> {
+        self.coords().into_iter().map(tile_center).collect()
+    }
+}
+
+pub fn tile_center(coord: (i32, i32)) -> Vector3<f32> {
+    Vector3::new(
+        (coord.0 as f32) * TILE_SIZE + TILE_SIZE * 0.5,
+        (coord.1 as f32) * TILE_SIZE + TILE_SIZE * 0.5,
+        0.0,
+    )
+}
+
+// Snap a world position to the coordinate of the tile it lies in
+pub fn world_to_tile(position: &Vector3<f32>) -> (i32, i32) {
+    (
+        (position.x / TILE_SIZE).floor() as i32,
+        (position.y / TILE_SIZE).floor() as i32,
+    )
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct TileMap {
     pub tiles: Vec<TileType>,
     pub width: i32,
     pub height: i32,
+    // Tiles set since the last `take_changed`, so the floor knows what to redraw
+    #[serde(skip)]
+    changed: Vec<(i32, i32)>,
 }
 
 impl TileMap {
@@ -28,6 +106,7 @@ impl TileMap {
             tiles: vec![tile; (width * height) as usize],
             width,
             height,
+            changed: Vec::new(),
         }
     }
 
@@ -58,28 +137,225 @@ impl TileMap {
     pub fn set(&mut self, coord: (i32, i32), tile: TileType) -> Option<TileType> {
         if self.is_within(coord) {
             self.tiles[coord_to_index(coord, self.width)] = tile;
+            self.changed.push(coord);
             Some(tile)
         } else {
             None
         }
     }
 
+    pub fn take_changed(&mut self) -> Vec<(i32, i32)> {
+        mem::replace(&mut self.changed, Vec::new())
+    }
+
     pub fn is_within(&self, coord: (i32, i32)) -> bool {
         coord.0 >= 0 && coord.0 < self.width && coord.1 >= 0 && coord.1 < self.height
     }
 }
 
+// One character per tile, with the top row of the map first
+impl fmt::Display for TileMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                write!(f, "{}", self.get((x, y)).unwrap().symbol())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Build a map and its enemy path back from a drawing like `TileMap`'s
+// `Display`. The path is followed through path tiles from the spawn to the
+// goal, so it must not branch or touch itself.
+pub fn parse_map(text: &str) -> Result<(TileMap, EnemyPath), Error> {
+    let rows = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<_>>();
+    let height = rows.len() as i32;
+    let width = rows.first().map_or(0, |row| row.chars().count()) as i32;
+    let mut tile_map = TileMap::new(width, height, TileType::Grass);
+    let mut start = None;
+    let mut end = None;
+    for (row_index, row) in rows.iter().enumerate() {
+        let y = height - 1 - row_index as i32;
+        if row.chars().count() as i32 != width {
+            return Err(Error::from_string(format!(
+                "Map row {} is not {} tiles wide",
+                row_index + 1,
+                width
+            )));
+        }
+        for (x, symbol) in row.chars().enumerate() {
+            let coord = (x as i32, y);
+            let tile = TileType::from_symbol(symbol).ok_or_else(|| {
+                Error::from_string(format!("Unknown map tile '{}' at {:?}", symbol, coord))
+            })?;
+            if tile == TileType::Spawn || tile == TileType::Goal {
+                let marker = if tile == TileType::Spawn {
+                    &mut start
+                } else {
+                    &mut end
+                };
+                if marker.replace(coord).is_some() {
+                    return Err(Error::from_string(format!(
+                        "Map has more than one '{}'",
+                        symbol
+                    )));
+                }
+            }
+            tile_map.set(coord, tile);
+        }
+    }
+
+    let starting_coord = start.ok_or_else(|| Error::from_string("Map has no spawn"))?;
+    let end = end.ok_or_else(|| Error::from_string("Map has no goal"))?;
+    let mut path = vec![];
+    let mut visited = HashSet::new();
+    visited.insert(starting_coord);
+    let mut cursor_coord = starting_coord;
+    while cursor_coord != end {
+        let next_steps = ALL_DIRECTIONS
+            .iter()
+            .map(|&direction| {
+                let movement_coord = direction_to_coord(direction);
+                let next_coord = (
+                    cursor_coord.0 + movement_coord.0,
+                    cursor_coord.1 + movement_coord.1,
+                );
+                (direction, next_coord)
+            })
+            .filter(|(_, next_coord)| {
+                !visited.contains(next_coord)
+                    && tile_map.get(*next_coord).map_or(false, TileType::is_path)
+            })
+            .collect::<Vec<_>>();
+        match next_steps.as_slice() {
+            [(direction, next_coord)] => {
+                path.push(*direction);
+                visited.insert(*next_coord);
+                cursor_coord = *next_coord;
+            }
+            [] => {
+                return Err(Error::from_string(format!(
+                    "Path ends at {:?} before reaching the goal",
+                    cursor_coord
+                )));
+            }
+            _ => {
+                return Err(Error::from_string(format!(
+                    "Path branches at {:?}",
+                    cursor_coord
+                )));
+            }
+        }
+    }
+
+    Ok((
+        tile_map,
+        EnemyPath {
+            starting_coord,
+            path,
+        },
+    ))
+}
+
 fn coord_to_index(coord: (i32, i32), width: i32) -> usize {
     (coord.0 + coord.1 * width) as usize
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
 pub enum TileType {
     Grass,
+    Path,
     Rock,
+    Water,
+    // A path crossing water
+    Bridge,
+    // Where an enemy path starts
+    Spawn,
+    // Where an enemy path ends
+    Goal,
+}
+
+const TILE_SYMBOLS: [(TileType, char); 7] = [
+    (TileType::Grass, '.'),
+    (TileType::Path, '#'),
+    (TileType::Rock, '^'),
+    (TileType::Water, '~'),
+    (TileType::Bridge, '='),
+    (TileType::Spawn, 'S'),
+    (TileType::Goal, 'E'),
+];
+
+impl TileType {
+    // Whether enemy paths are drawn with the tile
+    pub fn is_path(self) -> bool {
+        match self {
+            TileType::Path | TileType::Bridge | TileType::Spawn | TileType::Goal => true,
+            TileType::Grass | TileType::Rock | TileType::Water => false,
+        }
+    }
+
+    // The character the tile is drawn as in text maps
+    pub fn symbol(self) -> char {
+        TILE_SYMBOLS
+            .iter()
+            .find(|(tile, _)| *tile == self)
+            .map(|(_, symbol)| *symbol)
+            .unwrap()
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        TILE_SYMBOLS
+            .iter()
+            .find(|(_, tile_symbol)| *tile_symbol == symbol)
+            .map(|(tile, _)| *tile)
+    }
+}
+
+/** How a kind of tile looks and what can happen on it */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TileDefinition {
+    // Index into the floor tile sprite sheet
+    pub sprite_number: usize,
+    // Drawn on top of `sprite_number`, for tiles that share a base
+    #[serde(default)]
+    pub overlay_sprite_number: Option<usize>,
+    // Whether towers can be built on the tile
+    #[serde(default)]
+    pub buildable: bool,
+    // Whether enemy paths can cross the tile
+    #[serde(default)]
+    pub walkable: bool,
+}
+
+/** Every tile type's definition, loaded from `assets/tiles.ron` */
+#[derive(Default, Deserialize, Serialize)]
+pub struct TileCatalog {
+    pub tiles: BTreeMap<TileType, TileDefinition>,
+}
+
+impl TileCatalog {
+    pub fn get(&self, tile: TileType) -> Option<&TileDefinition> {
+        self.tiles.get(&tile)
+    }
+
+    pub fn is_buildable(&self, tile: TileType) -> bool {
+        self.get(tile)
+            .map_or(false, |definition| definition.buildable)
+    }
+
+    pub fn is_walkable(&self, tile: TileType) -> bool {
+        self.get(tile)
+            .map_or(false, |definition| definition.walkable)
+    }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum Direction {
     Up,
     Down,
@@ -98,7 +374,7 @@ impl Distribution<Direction> for Standard {
     }
 }
 
-fn direction_to_coord(dir: Direction) -> (i32, i32) {
+pub fn direction_to_coord(dir: Direction) -> (i32, i32) {
     match dir {
         Direction::Up => (0, 1),
         Direction::Down => (0, -1),
@@ -107,91 +383,104 @@ fn direction_to_coord(dir: Direction) -> (i32, i32) {
     }
 }
 
-pub fn generate_map(width: i32, height: i32) -> (TileMap, EnemyPath) {
-    let mut tile_map = TileMap::new(width, height, TileType::Grass);
-    let mut rng = rand::thread_rng();
-    let mut path = vec![];
-    let starting_coord = (rng.gen_range(0, width), rng.gen_range(0, height));
-    let mut cursor_coord = starting_coord;
-    let mut current_direction = *ALL_DIRECTIONS.choose(&mut rng).unwrap();
-    // place first tile
-    tile_map.set(cursor_coord, TileType::Rock);
-
-    let mut reached_end = false;
-    while !reached_end {
-        // Filter out the directions we can't move in
-        let valid_directions = ALL_DIRECTIONS
-            .iter()
-            .filter(|&dir| {
-                let direction_coord = direction_to_coord(*dir);
-                let test_coord = (
-                    cursor_coord.0 + direction_coord.0,
-                    cursor_coord.1 + direction_coord.1,
-                );
-                // Make sure we aren't overlapping a previous part of the path
-                let is_not_rock = tile_map
-                    .get(test_coord)
-                    .map_or(false, |tile| tile != TileType::Rock);
-                // Make sure we aren't touching a previous part of the path
-                let is_not_rock_adjacent = tile_map
-                    .get_neighbors(test_coord)
-                    .iter()
-                    .filter(|&tile| *tile == TileType::Rock)
-                    .count()
-                    <= 1;
-                is_not_rock && is_not_rock_adjacent
-            })
-            .collect::<Vec<_>>();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let can_move_forward = valid_directions.contains(&&current_direction);
-        let r = rng.gen::<f32>();
-        if can_move_forward && r < 0.6 {
-            // Keep moving forward
-            add_path_tile(
-                &current_direction,
-                &mut cursor_coord,
-                &mut path,
-                &mut tile_map,
-            );
-        } else {
-            // Pivot to a random (valid) direction
-            match valid_directions.choose(&mut rng) {
-                Some(&random_direction) => {
-                    current_direction = *random_direction;
-                    add_path_tile(
-                        &current_direction,
-                        &mut cursor_coord,
-                        &mut path,
-                        &mut tile_map,
-                    );
-                }
-                None => {
-                    // No more valid directions; end the path
-                    reached_end = true;
-                }
-            }
+    const MAP: &str = "\
+..~~~....
+S##=###..
+.^~~~.#^.
+......##E
+";
+
+    fn parse_error(text: &str) -> String {
+        match parse_map(text) {
+            Ok(_) => panic!("{:?} parsed", text),
+            Err(error) => error.to_string(),
         }
     }
-    (
-        tile_map,
-        EnemyPath {
-            starting_coord,
-            path,
-        },
-    )
-}
 
-fn add_path_tile(
-    direction: &Direction,
-    cursor_coord: &mut (i32, i32),
-    path: &mut Vec<Direction>,
-    tile_map: &mut TileMap,
-) {
-    let movement_coord = direction_to_coord(*direction);
-    *cursor_coord = (
-        (*cursor_coord).0 + movement_coord.0,
-        (*cursor_coord).1 + movement_coord.1,
-    );
-    path.push(*direction);
-    tile_map.set(*cursor_coord, TileType::Rock);
+    #[test]
+    fn parses_what_display_prints() {
+        let (tile_map, enemy_path) = parse_map(MAP).unwrap();
+        assert_eq!((tile_map.width, tile_map.height), (9, 4));
+        assert_eq!(tile_map.get((0, 2)), Some(TileType::Spawn));
+        assert_eq!(tile_map.get((3, 2)), Some(TileType::Bridge));
+        assert_eq!(tile_map.get((1, 1)), Some(TileType::Rock));
+        assert_eq!(
+            enemy_path.coords(),
+            vec![
+                (0, 2),
+                (1, 2),
+                (2, 2),
+                (3, 2),
+                (4, 2),
+                (5, 2),
+                (6, 2),
+                (6, 1),
+                (6, 0),
+                (7, 0),
+                (8, 0)
+            ]
+        );
+
+        let printed = tile_map.to_string();
+        assert_eq!(printed, MAP);
+        let (reparsed_map, reparsed_path) = parse_map(&printed).unwrap();
+        assert_eq!(reparsed_map.tiles, tile_map.tiles);
+        assert_eq!(reparsed_path.coords(), enemy_path.coords());
+    }
+
+    #[test]
+    fn builds_paths_from_neighboring_coords() {
+        let coords = vec![(2, 0), (2, 1), (3, 1), (3, 0), (2, 0)];
+        assert_eq!(EnemyPath::from_coords(&coords).unwrap().coords(), coords);
+        assert_eq!(
+            EnemyPath::from_coords(&[(4, 4)]).unwrap().coords(),
+            vec![(4, 4)]
+        );
+    }
+
+    #[test]
+    fn rejects_paths_that_skip_tiles() {
+        let error = EnemyPath::from_coords(&[]).err().unwrap();
+        assert!(error.to_string().contains("Path has no tiles"));
+        let error = EnemyPath::from_coords(&[(0, 0), (1, 0), (2, 1)])
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .contains("Path steps from (1, 0) to (2, 1), which aren't neighbors"));
+        let error = EnemyPath::from_coords(&[(0, 0), (0, 0)]).err().unwrap();
+        assert!(error.to_string().contains("which aren't neighbors"));
+    }
+
+    #[test]
+    fn rejects_ragged_rows() {
+        assert!(parse_error("S#E\n##\n").contains("Map row 2 is not 3 tiles wide"));
+    }
+
+    #[test]
+    fn rejects_unknown_symbols() {
+        assert!(parse_error("S?E").contains("Unknown map tile '?' at (1, 0)"));
+    }
+
+    #[test]
+    fn needs_exactly_one_spawn_and_goal() {
+        assert!(parse_error("..##E").contains("Map has no spawn"));
+        assert!(parse_error("S##..").contains("Map has no goal"));
+        assert!(parse_error("S#S#E").contains("Map has more than one 'S'"));
+        assert!(parse_error("S#E#E").contains("Map has more than one 'E'"));
+    }
+
+    #[test]
+    fn rejects_dead_ends() {
+        assert!(parse_error("S##.E").contains("Path ends at (2, 0) before reaching the goal"));
+    }
+
+    #[test]
+    fn rejects_branching_paths() {
+        assert!(parse_error(".#..\nS##E\n").contains("Path branches at (1, 0)"));
+    }
 }
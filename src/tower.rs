@@ -1,30 +1,322 @@
-use std::f32::INFINITY;
+use std::collections::BTreeMap;
 
 use amethyst::{
-    assets::Handle,
     core::{math::Vector3, timing::Time, transform::Transform},
     ecs::prelude::{
         Component, DenseVecStorage, Entities, Entity, Join, LazyUpdate, Read, ReadExpect,
-        ReadStorage, System, WriteStorage,
+        ReadStorage, System, WriteExpect, WriteStorage,
     },
-    prelude::*,
-    renderer::{SpriteRender, SpriteSheet},
+    renderer::{palette::Srgba, resources::Tint, SpriteRender},
 };
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     enemy::Enemy,
-    projectile::create_projectile,
+    path::PathFollower,
+    projectile::{create_projectile, ProjectileEffect, Trajectory},
+    seed::GameSeed,
+    spatial_grid::{distance_sq, SpatialGrid},
     sprite::{AssetType, SpriteSheetMap},
+    status_effect::StatusEffects,
+    velocity::Velocity,
+    wave::{WavePhase, WaveState},
 };
 
+// Refinements of a leading shot's flight time. Each one brings the aim closer
+// to the intercept point as long as the projectile is faster than its target.
+const INTERCEPT_ITERATIONS: usize = 8;
+
 #[derive(Component)]
 pub struct Tower {
-    speed: f32,
-    range: f32,
-    last_fire_time: f64,
+    pub kind: TowerKind,
+    pub targeting: TargetingMode,
+    // Number of upgrades bought
+    pub level: usize,
+    // The upgrade branch the tower committed to, if it got that far
+    pub branch: Option<usize>,
+    // The kind's definition with every upgrade bought so far applied
+    pub stats: TowerDefinition,
+    // Coins spent on building and upgrading the tower
+    pub invested: i32,
+    // The wave the tower was built ahead of, if it was built during a build
+    // phase
+    pub built_before_wave: Option<usize>,
+    // Seconds until the tower can fire again
+    cooldown_remaining: f32,
+    // Seconds until the tower looks for a better target
+    retarget_remaining: f32,
     target: Option<Entity>,
 }
 
+impl Tower {
+    // Coins the player gets back for selling the tower. Towers built during
+    // the current build phase are refunded in full.
+    pub fn sell_value(&self, sell_refund: f32, wave_state: &WaveState) -> i32 {
+        let built_this_build_phase = match wave_state.phase {
+            WavePhase::Build { .. } => self.built_before_wave == Some(wave_state.wave_number),
+            _ => false,
+        };
+        if built_this_build_phase {
+            self.invested
+        } else {
+            (self.invested as f32 * sell_refund).floor() as i32
+        }
+    }
+}
+
+/** The id a tower is listed under in `towers.ron` */
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct TowerKind(pub String);
+
+// Which enemy in range a tower shoots at
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum TargetingMode {
+    // Furthest along the path
+    First,
+    // Least far along the path
+    Last,
+    // Most health left
+    Strongest,
+    // Least health left
+    Weakest,
+    Closest,
+    // Any enemy in range, kept until it dies or leaves range
+    Random,
+}
+
+impl Default for TargetingMode {
+    fn default() -> Self {
+        TargetingMode::Closest
+    }
+}
+
+impl TargetingMode {
+    // The mode after this one when cycling through them in game
+    pub fn next(self) -> Self {
+        match self {
+            TargetingMode::First => TargetingMode::Last,
+            TargetingMode::Last => TargetingMode::Strongest,
+            TargetingMode::Strongest => TargetingMode::Weakest,
+            TargetingMode::Weakest => TargetingMode::Closest,
+            TargetingMode::Closest => TargetingMode::Random,
+            TargetingMode::Random => TargetingMode::First,
+        }
+    }
+
+    fn pick<R: Rng>(self, candidates: &[TargetCandidate], rng: &mut R) -> Option<Entity> {
+        if self == TargetingMode::Random {
+            return candidates.choose(rng).map(|candidate| candidate.entity);
+        }
+        // Lower scores are better
+        let score = |candidate: &TargetCandidate| match self {
+            TargetingMode::First => -candidate.distance_travelled,
+            TargetingMode::Last => candidate.distance_travelled,
+            TargetingMode::Strongest => -candidate.health as f32,
+            TargetingMode::Weakest => candidate.health as f32,
+            TargetingMode::Closest | TargetingMode::Random => candidate.distance_sq,
+        };
+        candidates
+            .iter()
+            .min_by(|a, b| score(a).partial_cmp(&score(b)).unwrap())
+            .map(|candidate| candidate.entity)
+    }
+}
+
+// Where a tower aims its straight shots
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum AimMode {
+    // At the target's current position
+    Direct,
+    // At where the projectile will meet the target if it keeps moving the way
+    // it is now
+    Lead,
+}
+
+impl Default for AimMode {
+    fn default() -> Self {
+        AimMode::Direct
+    }
+}
+
+// An enemy in range of a tower
+struct TargetCandidate {
+    entity: Entity,
+    health: i32,
+    distance_travelled: f32,
+    distance_sq: f32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TowerDefinition {
+    pub cost: i32,
+    // Seconds between shots
+    pub cooldown: f32,
+    pub range: f32,
+    // In units per second
+    pub projectile_speed: f32,
+    pub projectile_effects: Vec<ProjectileEffect>,
+    // Diameter of the projectile in world units
+    #[serde(default = "default_projectile_size")]
+    pub projectile_size: f32,
+    // How many enemies a projectile passes through before it is used up. It
+    // hits `pierce + 1` enemies in total.
+    #[serde(default)]
+    pub pierce: u32,
+    #[serde(default)]
+    pub trajectory: Trajectory,
+    // Only used by straight shots. Eased ones always aim where the target
+    // will be when they land and homing ones fly at the target itself.
+    #[serde(default)]
+    pub aim: AimMode,
+    pub sprite_sheet: AssetType,
+    pub sprite_number: usize,
+    // Projectiles are drawn from the same sprite sheet as the tower
+    pub projectile_sprite_number: usize,
+    #[serde(default)]
+    pub tint: Option<[f32; 4]>,
+    #[serde(default)]
+    pub targeting: TargetingMode,
+    // Seconds between looking for a better target
+    #[serde(default = "default_retarget_interval")]
+    pub retarget_interval: f32,
+    #[serde(default)]
+    pub upgrades: UpgradeTree,
+}
+
+impl TowerDefinition {
+    // The stats of a tower of this kind after buying `upgrades` in order
+    pub fn upgraded<'a>(&self, upgrades: impl Iterator<Item = &'a TowerUpgrade>) -> Self {
+        let mut definition = self.clone();
+        for upgrade in upgrades {
+            definition.cooldown *= upgrade.cooldown_multiplier;
+            definition.range += upgrade.range_bonus;
+            for effect in definition.projectile_effects.iter_mut() {
+                effect.add_damage(upgrade.damage_bonus);
+            }
+            definition
+                .projectile_effects
+                .extend(upgrade.extra_effects.iter().cloned());
+            definition.sprite_number = upgrade.sprite_number.unwrap_or(definition.sprite_number);
+            definition.tint = upgrade.tint.or(definition.tint);
+        }
+        definition
+    }
+}
+
+/** One level a tower can be upgraded to */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TowerUpgrade {
+    pub name: String,
+    pub cost: i32,
+    // Multiplies the time between shots
+    #[serde(default = "default_multiplier")]
+    pub cooldown_multiplier: f32,
+    #[serde(default)]
+    pub range_bonus: f32,
+    // Added to every projectile effect that deals damage
+    #[serde(default)]
+    pub damage_bonus: i32,
+    // Applied after the projectile's existing effects
+    #[serde(default)]
+    pub extra_effects: Vec<ProjectileEffect>,
+    // How the tower looks from this level on
+    #[serde(default)]
+    pub sprite_number: Option<usize>,
+    #[serde(default)]
+    pub tint: Option<[f32; 4]>,
+}
+
+/** The upgrades available to a tower kind */
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UpgradeTree {
+    // Levels every tower of the kind goes through in order
+    #[serde(default)]
+    pub levels: Vec<TowerUpgrade>,
+    // Once every level is bought the tower picks one of these branches and
+    // can only upgrade along it from then on
+    #[serde(default)]
+    pub branches: Vec<UpgradeBranch>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UpgradeBranch {
+    pub name: String,
+    pub levels: Vec<TowerUpgrade>,
+}
+
+impl UpgradeTree {
+    // The upgrades a tower can buy next, each with the branch buying it
+    // commits the tower to
+    pub fn next_upgrades(
+        &self,
+        level: usize,
+        branch: Option<usize>,
+    ) -> Vec<(Option<usize>, &TowerUpgrade)> {
+        if let Some(upgrade) = self.levels.get(level) {
+            return vec![(None, upgrade)];
+        }
+        let branch_level = level - self.levels.len();
+        match branch {
+            Some(branch) => self
+                .branches
+                .get(branch)
+                .and_then(|chosen| chosen.levels.get(branch_level))
+                .map(|upgrade| vec![(Some(branch), upgrade)])
+                .unwrap_or_default(),
+            None => self
+                .branches
+                .iter()
+                .enumerate()
+                .filter_map(|(index, choice)| {
+                    choice.levels.first().map(|upgrade| (Some(index), upgrade))
+                })
+                .collect(),
+        }
+    }
+
+    // Every upgrade a tower at `level` on `branch` has bought, in order
+    pub fn bought(&self, level: usize, branch: Option<usize>) -> Vec<&TowerUpgrade> {
+        let branch_levels = branch
+            .and_then(|branch| self.branches.get(branch))
+            .map_or(&[][..], |chosen| chosen.levels.as_slice());
+        self.levels
+            .iter()
+            .chain(branch_levels.iter())
+            .take(level)
+            .collect()
+    }
+}
+
+fn default_retarget_interval() -> f32 {
+    0.5
+}
+
+fn default_projectile_size() -> f32 {
+    8.0
+}
+
+fn default_multiplier() -> f32 {
+    1.0
+}
+
+/** Every tower the player can build, loaded from `assets/towers.ron` */
+#[derive(Default, Deserialize, Serialize)]
+pub struct TowerCatalog {
+    // Tower kinds in the order of the build hotkeys
+    pub build_menu: Vec<TowerKind>,
+    // Fraction of the coins invested in a tower that selling it returns
+    pub sell_refund: f32,
+    pub towers: BTreeMap<TowerKind, TowerDefinition>,
+}
+
+impl TowerCatalog {
+    pub fn get(&self, kind: &TowerKind) -> Option<&TowerDefinition> {
+        self.towers.get(kind)
+    }
+}
+
 pub struct TowerSystem;
 
 impl<'s> System<'s> for TowerSystem {
@@ -32,98 +324,222 @@ impl<'s> System<'s> for TowerSystem {
         WriteStorage<'s, Tower>,
         ReadStorage<'s, Transform>,
         ReadStorage<'s, Enemy>,
+        ReadStorage<'s, PathFollower>,
+        ReadStorage<'s, StatusEffects>,
+        ReadStorage<'s, Velocity>,
         ReadExpect<'s, LazyUpdate>,
         Read<'s, SpriteSheetMap>,
         Read<'s, Time>,
+        WriteExpect<'s, GameSeed>,
+        Read<'s, SpatialGrid>,
         Entities<'s>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut towers, transforms, enemies, lazy_update, sprite_sheet_map, time, entities) = data;
-        let sprite_sheet = sprite_sheet_map.get(AssetType::Floor).unwrap();
+        let (
+            mut towers,
+            transforms,
+            enemies,
+            path_followers,
+            status_effects,
+            velocities,
+            lazy_update,
+            sprite_sheet_map,
+            time,
+            mut game_seed,
+            spatial_grid,
+            entities,
+        ) = data;
 
         for (transform, tower) in (&transforms, &mut towers).join() {
-            if let Some(enemy_entity) = tower.target {
-                let enemy_transform = match transforms.get(enemy_entity).cloned() {
-                    Some(e) => e,
-                    None => {
-                        tower.target = None;
-                        continue;
-                    }
-                };
-                // If the target is too far away, stop targeting it
-                if !in_range(
-                    transform.translation(),
-                    enemy_transform.translation(),
-                    tower.range,
-                ) {
-                    tower.target = None;
-                    continue;
-                }
-                // Projectile firing
-                let current_time = time.absolute_time_seconds();
-                if tower.last_fire_time + (tower.speed as f64) < current_time {
-                    tower.last_fire_time = current_time;
-                    create_projectile(
-                        &entities,
-                        &lazy_update,
-                        sprite_sheet.clone(),
-                        *transform.translation(),
-                        *enemy_transform.translation(),
-                        1.0,
-                    );
+            let definition = &tower.stats;
+            tower.cooldown_remaining = (tower.cooldown_remaining - time.fixed_seconds()).max(0.0);
+            tower.retarget_remaining -= time.fixed_seconds();
+
+            // Drop targets that died or left range
+            let target_is_valid = tower.target.map_or(false, |target| {
+                entities.is_alive(target)
+                    && enemies.get(target).map_or(false, |enemy| enemy.health > 0)
+                    && transforms.get(target).map_or(false, |enemy_transform| {
+                        in_range(
+                            transform.translation(),
+                            enemy_transform.translation(),
+                            definition.range,
+                        )
+                    })
+            });
+            if !target_is_valid {
+                tower.target = None;
+            }
+
+            // Random targets are kept for as long as they're valid, the other
+            // modes switch to a better target every `retarget_interval`
+            let retarget = match tower.target {
+                None => true,
+                Some(_) => {
+                    tower.targeting != TargetingMode::Random && tower.retarget_remaining <= 0.0
                 }
-            } else {
-                // Iterate over enemies and set closest one as target
-                let mut closest_enemy: Option<Entity> = None;
-                let mut closest_len = INFINITY;
-                for (entity, _enemy, enemy_transform) in (&entities, &enemies, &transforms).join() {
-                    let len_sq = len_sq(&(enemy_transform.translation() - transform.translation()));
-                    if in_range(
-                        transform.translation(),
-                        enemy_transform.translation(),
-                        tower.range,
-                    ) {
-                        if len_sq < closest_len {
-                            closest_enemy = Some(entity);
-                            closest_len = len_sq;
+            };
+            if retarget {
+                tower.retarget_remaining = definition.retarget_interval;
+                let candidates = spatial_grid
+                    .within_radius(transform.translation(), definition.range)
+                    .into_iter()
+                    .filter_map(|entity| {
+                        let enemy = enemies.get(entity)?;
+                        let enemy_transform = transforms.get(entity)?;
+                        let enemy_position = enemy_transform.translation();
+                        if enemy.health <= 0
+                            || !in_range(transform.translation(), enemy_position, definition.range)
+                        {
+                            return None;
                         }
+                        Some(TargetCandidate {
+                            entity,
+                            health: enemy.health,
+                            distance_travelled: path_followers
+                                .get(entity)
+                                .map_or(0.0, |path_follower| path_follower.distance_travelled),
+                            distance_sq: distance_sq(enemy_position, transform.translation()),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                tower.target = tower.targeting.pick(&candidates, &mut game_seed.rng);
+            }
+
+            let enemy_entity = match tower.target {
+                Some(enemy_entity) => enemy_entity,
+                None => continue,
+            };
+            let enemy_transform = match transforms.get(enemy_entity) {
+                Some(enemy_transform) => enemy_transform,
+                None => continue,
+            };
+            // Projectile firing
+            if tower.cooldown_remaining <= 0.0 {
+                tower.cooldown_remaining = definition.cooldown;
+                let motion = TargetMotion {
+                    position: *enemy_transform.translation(),
+                    path_follower: path_followers.get(enemy_entity),
+                    status_effects: status_effects.get(enemy_entity),
+                    velocity: velocities.get(enemy_entity),
+                };
+                let aim = match (&definition.trajectory, definition.aim) {
+                    (Trajectory::Straight, AimMode::Direct) => motion.position,
+                    (Trajectory::Straight, AimMode::Lead) => {
+                        motion.intercept_point(transform.translation(), definition.projectile_speed)
                     }
-                }
-                tower.target = closest_enemy;
+                    // Eased shots land after a fixed time, so aim at where
+                    // the target will be by then
+                    (Trajectory::Eased { flight_time, .. }, _) => {
+                        motion.position_after(*flight_time)
+                    }
+                    // Homing shots start out flying at the target and steer
+                    // from there
+                    (Trajectory::Homing { .. }, _) => motion.position,
+                };
+                create_projectile(
+                    &entities,
+                    &lazy_update,
+                    sprite_sheet_map
+                        .get(definition.sprite_sheet)
+                        .unwrap()
+                        .clone(),
+                    *transform.translation(),
+                    aim,
+                    enemy_entity,
+                    definition,
+                );
             }
         }
     }
 }
 
-fn len_sq(v: &Vector3<f32>) -> f32 {
-    v.x * v.x + v.y * v.y
+fn in_range(tower: &Vector3<f32>, enemy: &Vector3<f32>, range: f32) -> bool {
+    distance_sq(enemy, tower) < (range * range)
 }
 
-fn in_range(tower: &Vector3<f32>, enemy: &Vector3<f32>, range: f32) -> bool {
-    len_sq(&(enemy - tower)) < (range * range)
+// How a tower's target is moving
+struct TargetMotion<'a> {
+    position: Vector3<f32>,
+    path_follower: Option<&'a PathFollower>,
+    status_effects: Option<&'a StatusEffects>,
+    velocity: Option<&'a Velocity>,
 }
 
-pub fn create_tower(world: &mut World, sprite_sheet: Handle<SpriteSheet>, position: Vector3<f32>) {
+impl<'a> TargetMotion<'a> {
+    // Where the target will be after `seconds`, assuming it keeps its current
+    // speed
+    fn position_after(&self, seconds: f32) -> Vector3<f32> {
+        match (self.path_follower, self.velocity) {
+            (Some(path_follower), _) => {
+                let speed_multiplier = self
+                    .status_effects
+                    .map_or(1.0, |effects| effects.speed_multiplier());
+                path_follower.position_after(
+                    &self.position,
+                    path_follower.speed * speed_multiplier * seconds,
+                )
+            }
+            (None, Some(velocity)) => self.position + velocity.vector * seconds,
+            (None, None) => self.position,
+        }
+    }
+
+    // Where a projectile fired from `origin` at `projectile_speed` meets the
+    // target
+    fn intercept_point(&self, origin: &Vector3<f32>, projectile_speed: f32) -> Vector3<f32> {
+        if projectile_speed <= 0.0 {
+            return self.position;
+        }
+        let mut aim = self.position;
+        for _ in 0..INTERCEPT_ITERATIONS {
+            let flight_time = distance_sq(&aim, origin).sqrt() / projectile_speed;
+            aim = self.position_after(flight_time);
+        }
+        aim
+    }
+}
+
+pub fn create_tower(
+    entities: &Entities,
+    lazy_update: &ReadExpect<LazyUpdate>,
+    sprite_sheet_map: &SpriteSheetMap,
+    position: Vector3<f32>,
+    kind: TowerKind,
+    definition: &TowerDefinition,
+    built_before_wave: Option<usize>,
+) -> Entity {
     let mut transform = Transform::default();
     transform.set_translation(position);
 
     let tower = Tower {
-        speed: 1.0,
-        range: 100.0,
-        last_fire_time: 0.0,
+        kind,
+        targeting: definition.targeting,
+        level: 0,
+        branch: None,
+        stats: definition.clone(),
+        invested: definition.cost,
+        built_before_wave,
+        cooldown_remaining: 0.0,
+        retarget_remaining: 0.0,
         target: None,
     };
 
-    let grass_sprite = SpriteRender {
-        sprite_sheet: sprite_sheet.clone(),
-        sprite_number: 5,
+    let tower_sprite = SpriteRender {
+        sprite_sheet: sprite_sheet_map
+            .get(definition.sprite_sheet)
+            .unwrap()
+            .clone(),
+        sprite_number: definition.sprite_number,
     };
 
-    world
-        .create_entity()
-        .with(grass_sprite.clone())
-        .with(tower)
-        .with(transform.clone())
-        .build();
+    let entity = entities.create();
+    lazy_update.insert(entity, tower_sprite);
+    lazy_update.insert(entity, tower);
+    lazy_update.insert(entity, transform);
+    if let Some([r, g, b, a]) = definition.tint {
+        lazy_update.insert(entity, Tint(Srgba::new(r, g, b, a)));
+    }
+    entity
 }
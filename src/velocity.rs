@@ -1,10 +1,11 @@
 use amethyst::{
-    core::{math::Vector3, transform::Transform},
-    ecs::prelude::{Component, DenseVecStorage, Join, ReadStorage, System, WriteStorage},
+    core::{math::Vector3, timing::Time, transform::Transform},
+    ecs::prelude::{Component, DenseVecStorage, Join, Read, ReadStorage, System, WriteStorage},
 };
 
 #[derive(Component)]
 pub struct Velocity {
+    // In units per second
     pub vector: Vector3<f32>,
 }
 
@@ -17,12 +18,16 @@ impl Velocity {
 pub struct VelocitySystem;
 
 impl<'a> System<'a> for VelocitySystem {
-    type SystemData = (WriteStorage<'a, Transform>, ReadStorage<'a, Velocity>);
+    type SystemData = (
+        WriteStorage<'a, Transform>,
+        ReadStorage<'a, Velocity>,
+        Read<'a, Time>,
+    );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut transforms, velocities) = data;
+        let (mut transforms, velocities, time) = data;
         for (transform, velocity) in (&mut transforms, &velocities).join() {
-            transform.append_translation(velocity.vector);
+            transform.append_translation(velocity.vector * time.fixed_seconds());
         }
     }
 }
@@ -0,0 +1,388 @@
+use std::{cmp::Ordering, collections::VecDeque};
+
+use amethyst::{
+    core::{timing::Time, transform::Transform},
+    ecs::prelude::{
+        Entities, Join, LazyUpdate, Read, ReadExpect, ReadStorage, System, WriteExpect,
+    },
+    error::Error,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    endless::EndlessConfig,
+    enemy::{create_enemy, Enemy, EnemyCatalog, EnemyType},
+    seed::GameSeed,
+    spawner::{Spawner, SpawnerId},
+    sprite::SpriteSheetMap,
+};
+
+/** A run of identical enemies within a wave */
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct WaveGroup {
+    pub enemy: EnemyType,
+    pub count: u32,
+    // Seconds between two enemies of the group
+    pub spacing: f32,
+    // Seconds after the wave starts before the first enemy of the group spawns
+    #[serde(default)]
+    pub delay: f32,
+    #[serde(default)]
+    pub spawner: SpawnerId,
+    // Elites are tougher and worth more, see `EliteModifiers`
+    #[serde(default)]
+    pub elite: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct WaveDefinition {
+    // Overrides the list's `build_time` for the build phase before this wave
+    #[serde(default)]
+    pub build_time: Option<f32>,
+    pub groups: Vec<WaveGroup>,
+}
+
+/** Every wave of a game in order, loaded from `assets/waves.ron` */
+#[derive(Default, Deserialize, Serialize)]
+pub struct WaveList {
+    // Seconds the player gets to build before each wave
+    pub build_time: f32,
+    pub waves: Vec<WaveDefinition>,
+}
+
+impl WaveList {
+    fn build_time(&self, wave_number: usize) -> f32 {
+        self.waves
+            .get(wave_number - 1)
+            .and_then(|wave| wave.build_time)
+            .unwrap_or(self.build_time)
+    }
+
+    // Catches groups naming an enemy or spawner that doesn't exist before the
+    // game starts rather than in the middle of a wave
+    pub fn validate(
+        &self,
+        enemy_catalog: &EnemyCatalog,
+        spawners: &[SpawnerId],
+    ) -> Result<(), Error> {
+        check_seconds("The wave list's build_time", self.build_time)?;
+        for (index, wave) in self.waves.iter().enumerate() {
+            if let Some(build_time) = wave.build_time {
+                check_seconds(&format!("Wave {}'s build_time", index + 1), build_time)?;
+            }
+            for group in wave.groups.iter() {
+                let name = format!("Wave {}'s {:?} group", index + 1, group.enemy.0);
+                check_seconds(&format!("{} spacing", name), group.spacing)?;
+                check_seconds(&format!("{} delay", name), group.delay)?;
+                if enemy_catalog.get(&group.enemy).is_none() {
+                    return Err(Error::from_string(format!(
+                        "Wave {} has a group of unknown enemy {:?}",
+                        index + 1,
+                        group.enemy.0
+                    )));
+                }
+                if !spawners.contains(&group.spawner) {
+                    return Err(Error::from_string(format!(
+                        "Wave {} spawns {:?} at spawner {}, which the level doesn't have",
+                        index + 1,
+                        group.enemy.0,
+                        group.spawner.0
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Times in a wave list can't run backwards, and NaN can't be put in order
+fn check_seconds(name: &str, seconds: f32) -> Result<(), Error> {
+    if seconds.is_finite() && seconds >= 0.0 {
+        Ok(())
+    } else {
+        Err(Error::from_string(format!(
+            "{} is {}, it must be a number of seconds of at least 0",
+            name, seconds
+        )))
+    }
+}
+
+/** Where the waves of a game come from */
+pub enum WaveSource {
+    // The waves of `assets/waves.ron`, ending in victory after the last one
+    Scripted(WaveList),
+    // Generated waves that keep coming until the player runs out of lives
+    Endless(EndlessConfig),
+}
+
+impl WaveSource {
+    pub fn total_waves(&self) -> Option<usize> {
+        match self {
+            WaveSource::Scripted(wave_list) => Some(wave_list.waves.len()),
+            WaveSource::Endless(_) => None,
+        }
+    }
+
+    fn build_time(&self, wave_number: usize) -> f32 {
+        match self {
+            WaveSource::Scripted(wave_list) => wave_list.build_time(wave_number),
+            WaveSource::Endless(config) => config.build_time,
+        }
+    }
+
+    fn wave(
+        &self,
+        wave_number: usize,
+        enemy_catalog: &EnemyCatalog,
+        spawners: &[SpawnerId],
+        game_seed: &GameSeed,
+    ) -> Option<WaveDefinition> {
+        match self {
+            WaveSource::Scripted(wave_list) => wave_list.waves.get(wave_number - 1).cloned(),
+            WaveSource::Endless(config) => Some(config.generate_wave(
+                wave_number,
+                enemy_catalog,
+                spawners,
+                &mut game_seed.wave_rng(wave_number),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WavePhase {
+    // Counting down to the start of the wave
+    Build { time_remaining: f32 },
+    // Enemies of the wave are spawning or still alive
+    Active,
+    // Every enemy of the wave is dead or leaked
+    Cleared,
+    // The final wave was cleared
+    Victory,
+}
+
+struct ScheduledSpawn {
+    // Seconds after the start of the wave
+    time: f32,
+    enemy_type: EnemyType,
+    spawner: SpawnerId,
+    elite: bool,
+}
+
+/** Progress through the wave list */
+pub struct WaveState {
+    pub phase: WavePhase,
+    // 1-based number of the wave being built for, fought or just cleared
+    pub wave_number: usize,
+    // None in endless mode
+    pub total_waves: Option<usize>,
+    // Enemies of the current wave that are yet to spawn or still alive
+    pub enemies_remaining: usize,
+    // Seconds since the current wave started
+    wave_time: f32,
+    spawn_queue: VecDeque<ScheduledSpawn>,
+}
+
+impl WaveState {
+    pub fn new(wave_source: &WaveSource) -> Self {
+        WaveState {
+            phase: WavePhase::Build {
+                time_remaining: wave_source.build_time(1),
+            },
+            wave_number: 1,
+            total_waves: wave_source.total_waves(),
+            enemies_remaining: 0,
+            wave_time: 0.0,
+            spawn_queue: VecDeque::new(),
+        }
+    }
+
+    fn start_wave(&mut self, wave: Option<WaveDefinition>) {
+        let mut spawns = Vec::new();
+        for group in wave.iter().flat_map(|wave| wave.groups.iter()) {
+            for index in 0..group.count {
+                spawns.push(ScheduledSpawn {
+                    time: group.delay + group.spacing * index as f32,
+                    enemy_type: group.enemy.clone(),
+                    spawner: group.spawner,
+                    elite: group.elite,
+                });
+            }
+        }
+        // A stable sort keeps enemies spawning at the same moment in the order
+        // their groups are listed
+        spawns.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+
+        self.spawn_queue = spawns.into();
+        self.enemies_remaining = self.spawn_queue.len();
+        self.wave_time = 0.0;
+        self.phase = WavePhase::Active;
+    }
+
+    fn finish_wave(&mut self, wave_source: &WaveSource) {
+        match self.total_waves {
+            Some(total_waves) if self.wave_number >= total_waves => {
+                self.phase = WavePhase::Victory;
+            }
+            _ => {
+                self.wave_number += 1;
+                self.phase = WavePhase::Build {
+                    time_remaining: wave_source.build_time(self.wave_number),
+                };
+            }
+        }
+    }
+}
+
+pub struct WaveSystem;
+
+impl<'s> System<'s> for WaveSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Spawner>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Enemy>,
+        WriteExpect<'s, WaveState>,
+        ReadExpect<'s, WaveSource>,
+        ReadExpect<'s, EnemyCatalog>,
+        ReadExpect<'s, GameSeed>,
+        ReadExpect<'s, LazyUpdate>,
+        Read<'s, SpriteSheetMap>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            spawners,
+            transforms,
+            enemies,
+            mut wave_state,
+            wave_source,
+            enemy_catalog,
+            game_seed,
+            lazy_update,
+            sprite_sheet_map,
+            time,
+        ) = data;
+
+        match wave_state.phase {
+            WavePhase::Build { time_remaining } => {
+                let time_remaining = time_remaining - time.fixed_seconds();
+                if time_remaining <= 0.0 {
+                    let spawner_ids = (&spawners)
+                        .join()
+                        .map(|spawner| spawner.id)
+                        .collect::<Vec<_>>();
+                    let wave = wave_source.wave(
+                        wave_state.wave_number,
+                        &enemy_catalog,
+                        &spawner_ids,
+                        &game_seed,
+                    );
+                    wave_state.start_wave(wave);
+                } else {
+                    wave_state.phase = WavePhase::Build { time_remaining };
+                }
+            }
+            WavePhase::Active => {
+                let alive = (&enemies).join().count();
+                // Enemies spawned last step are already counted as alive
+                if wave_state.spawn_queue.is_empty() && alive == 0 {
+                    wave_state.enemies_remaining = 0;
+                    wave_state.phase = WavePhase::Cleared;
+                    return;
+                }
+
+                wave_state.wave_time += time.fixed_seconds();
+                let mut spawned = 0;
+                while wave_state
+                    .spawn_queue
+                    .front()
+                    .map_or(false, |spawn| spawn.time <= wave_state.wave_time)
+                {
+                    let spawn = wave_state.spawn_queue.pop_front().unwrap();
+                    let definition = match enemy_catalog.get(&spawn.enemy_type) {
+                        Some(definition) if spawn.elite => enemy_catalog.elite.apply(definition),
+                        Some(definition) => definition.clone(),
+                        None => {
+                            error!("Can't spawn unknown enemy {:?}", spawn.enemy_type.0);
+                            continue;
+                        }
+                    };
+                    let spawner = (&spawners, &transforms)
+                        .join()
+                        .find(|(spawner, _)| spawner.id == spawn.spawner);
+                    if let Some((spawner, transform)) = spawner {
+                        create_enemy(
+                            &entities,
+                            &lazy_update,
+                            sprite_sheet_map.clone(),
+                            *transform.translation(),
+                            spawner.waypoints.clone(),
+                            spawn.enemy_type,
+                            &definition,
+                        );
+                        spawned += 1;
+                    } else {
+                        error!(
+                            "Can't spawn {:?} at missing spawner {}",
+                            spawn.enemy_type.0, spawn.spawner.0
+                        );
+                    }
+                }
+                wave_state.enemies_remaining = wave_state.spawn_queue.len() + alive + spawned;
+            }
+            WavePhase::Cleared => wave_state.finish_wave(&wave_source),
+            WavePhase::Victory => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wave_list(spacing: f32, delay: f32, build_time: Option<f32>) -> WaveList {
+        WaveList {
+            build_time: 10.0,
+            waves: vec![WaveDefinition {
+                build_time,
+                groups: vec![WaveGroup {
+                    enemy: EnemyType("jumping_jelly".to_string()),
+                    count: 3,
+                    spacing,
+                    delay,
+                    spawner: SpawnerId(0),
+                    elite: false,
+                }],
+            }],
+        }
+    }
+
+    fn validate(wave_list: &WaveList) -> Result<(), Error> {
+        wave_list.validate(&EnemyCatalog::default(), &[SpawnerId(0)])
+    }
+
+    #[test]
+    fn rejects_times_that_cant_be_ordered() {
+        for wave_list in [
+            wave_list(std::f32::NAN, 0.0, None),
+            wave_list(1.0, -2.0, None),
+            wave_list(1.0, 0.0, Some(std::f32::INFINITY)),
+        ]
+        .iter()
+        {
+            let error = validate(wave_list).err().unwrap();
+            assert!(error.to_string().contains("it must be a number of seconds"));
+        }
+    }
+
+    #[test]
+    fn checks_times_before_enemies() {
+        // The catalog is empty, so a list with valid times fails on its enemy
+        let error = validate(&wave_list(1.0, 0.0, Some(5.0))).err().unwrap();
+        assert!(error.to_string().contains("unknown enemy"));
+    }
+}